@@ -0,0 +1,217 @@
+// Define structs and functions for generating Azure DNS-specific Terraform
+// output.  Azure has a separate resource type per record type, each with
+// its own way of holding values, and names records relative to the zone.
+use std::collections::HashMap;
+use serde_json;
+use types::{TinyDNSRecord, Route53Record};
+use provider::Provider;
+use zone;
+
+// Azure provider: PTRs go in the reverse zone, everything else in the
+// forward zone.  Zones are referred to by name, within a resource group.
+pub struct Azure {
+    pub fwdzone: String,
+    pub ptrzone: String,
+    pub resource_group: String
+}
+
+#[derive(Serialize)]
+pub struct AzureFile {
+    pub resource: HashMap<String, HashMap<String, AzureRecord>>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AzureRecord {
+    pub name: String,
+    pub zone_name: String,
+    pub resource_group_name: String,
+    pub ttl: u32,
+    // A, AAAA, NS and PTR take a plain list of values...
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub records: Option<Vec<String>>,
+    // ...while CNAME takes a single target and the rest nested blocks
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub record: Option<AzureValue>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum AzureValue {
+    Target(String),
+    Mx(Vec<MxBlock>),
+    Txt(Vec<TxtBlock>),
+    Srv(Vec<SrvBlock>),
+    Caa(Vec<CaaBlock>)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MxBlock {
+    pub preference: u16,
+    pub exchange: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TxtBlock {
+    pub value: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SrvBlock {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CaaBlock {
+    pub flags: u8,
+    pub tag: String,
+    pub value: String
+}
+
+impl Azure {
+    pub fn new(fwdzone: &str, ptrzone: &str, resource_group: &str) -> Self {
+        Azure {
+            fwdzone: zone::normalise(fwdzone),
+            ptrzone: zone::normalise(ptrzone),
+            resource_group: resource_group.to_string()
+        }
+    }
+}
+
+// Split a value into whitespace-separated fields, checking how many there
+// are and parsing the numeric ones
+fn fields(set: &Route53Record, value: &str, count: usize) -> Result<Vec<String>, String> {
+    let retval: Vec<String> = value.split_whitespace().map(|x| x.to_string()).collect();
+    match retval.len() == count {
+        true => Ok(retval),
+        false => Err(format!("Can't parse {} value '{}' for {}", set.rtype, value, set.name))
+    }
+}
+
+fn number<T: ::std::str::FromStr>(set: &Route53Record, field: &str) -> Result<T, String> {
+    field.parse::<T>().map_err(|_| format!("Bad number '{}' in {} record for {}",
+                                           field, set.rtype, set.name))
+}
+
+// Turn one record set into the Azure resource type and record for it
+fn record(set: &Route53Record, resource_group: &str) -> Result<(String, AzureRecord), String> {
+    if !zone::in_zone(&set.name, &set.zone_id) {
+        return Err(format!("{} record for {} is not in zone {}",
+                           set.rtype, set.name, set.zone_id));
+    }
+    let name = match zone::relative_name(&set.name, &set.zone_id).as_str() {
+        "" => "@".to_string(),
+        x  => x.to_string()
+    };
+    let mut retval = AzureRecord {
+        name,
+        zone_name:           set.zone_id.to_string(),
+        resource_group_name: resource_group.to_string(),
+        ttl:                 set.ttl,
+        records:             None,
+        record:              None
+    };
+
+    let host = |x: &str| x.trim_end_matches('.').to_string();
+    match set.rtype.as_str() {
+        "A" | "AAAA" => {
+            retval.records = Some(set.records.clone());
+        },
+        "NS" | "PTR" => {
+            retval.records = Some(set.records.iter().map(|x| host(x)).collect());
+        },
+        "CNAME" => {
+            if set.records.len() != 1 {
+                return Err(format!("CNAME {} has {} targets, Azure allows one",
+                                   set.name, set.records.len()));
+            }
+            retval.record = Some(AzureValue::Target(host(&set.records[0])));
+        },
+        "MX" => {
+            let mut blocks = Vec::new();
+            for v in &set.records {
+                let f = fields(set, v, 2)?;
+                blocks.push(MxBlock { preference: number(set, &f[0])?, exchange: host(&f[1]) });
+            }
+            retval.record = Some(AzureValue::Mx(blocks));
+        },
+        "TXT" => {
+            let blocks = set.records.iter().map(|v| TxtBlock { value: v.to_string() }).collect();
+            retval.record = Some(AzureValue::Txt(blocks));
+        },
+        "SRV" => {
+            let mut blocks = Vec::new();
+            for v in &set.records {
+                let f = fields(set, v, 4)?;
+                blocks.push(SrvBlock {
+                    priority: number(set, &f[0])?,
+                    weight:   number(set, &f[1])?,
+                    port:     number(set, &f[2])?,
+                    target:   host(&f[3])
+                });
+            }
+            retval.record = Some(AzureValue::Srv(blocks));
+        },
+        "CAA" => {
+            let mut blocks = Vec::new();
+            for v in &set.records {
+                let f: Vec<&str> = v.splitn(3, ' ').collect();
+                if f.len() != 3 {
+                    return Err(format!("Can't parse CAA value '{}' for {}", v, set.name));
+                }
+                blocks.push(CaaBlock {
+                    flags: number(set, f[0])?,
+                    tag:   f[1].to_string(),
+                    value: f[2].to_string()
+                });
+            }
+            retval.record = Some(AzureValue::Caa(blocks));
+        },
+        x => {
+            return Err(format!("Azure DNS has no resource for {} records ({})", x, set.name));
+        }
+    }
+
+    let rtype = format!("azurerm_dns_{}_record", set.rtype.to_lowercase());
+    Ok((rtype, retval))
+}
+
+impl Provider for Azure {
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String {
+        match rec.rtype.as_str() {
+            "PTR" => self.ptrzone.to_string(),
+            _     => self.fwdzone.to_string()
+        }
+    }
+
+    // Sort each record set into the resource type for its record type.
+    // The SOA belongs to the zone resource itself rather than a record, so
+    // it's skipped; anything else Azure can't hold is an error.  All the
+    // errors are collected up so they can be reported together.
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+        let mut outer_hash: HashMap<String, HashMap<String, AzureRecord>> = HashMap::new();
+        let mut errors = Vec::new();
+
+        for (key, set) in &sets {
+            if set.rtype == "SOA" {
+                println!("Warning: skipping SOA record for {}, Azure DNS manages it", set.name);
+                continue;
+            }
+            match record(set, &self.resource_group) {
+                Ok((rtype, r)) => {
+                    outer_hash.entry(rtype).or_default().insert(key.to_string(), r);
+                },
+                Err(e) => errors.push(e)
+            }
+        }
+
+        if !errors.is_empty() {
+            errors.sort();
+            return Err(errors.join("\n"));
+        }
+        let az_file = AzureFile { resource: outer_hash };
+        serde_json::to_string_pretty(&az_file).map_err(|e| e.to_string())
+    }
+}
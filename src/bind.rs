@@ -0,0 +1,557 @@
+// Define functions for reading and writing RFC 1035 master (BIND zone)
+// files, converting to and from TinyDNSRecords.
+use std::fs;
+use std::path::Path;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use types::TinyDNSRecord;
+use zone::{self, absolute};
+use tinydns;
+
+// How quote_strings writes the bytes it can't leave as they are: zone
+// files use \DDD decimal escapes, Route53 uses \NNN octal ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escape {
+    Decimal,
+    Octal
+}
+
+// Quote text as RFC 1035 character-strings.  These can't be longer than
+// 255 octets, so longer text is split into several quoted strings.
+// Quotes and backslashes are escaped and anything unprintable gets the
+// given style of three digit escape.
+pub fn quote_strings(text: &str, escape: Escape) -> String {
+    let bytes = text.as_bytes();
+    if bytes.is_empty() {
+        return "\"\"".to_string();
+    }
+    let mut strings = Vec::new();
+    for chunk in bytes.chunks(255) {
+        let mut s = String::from("\"");
+        for b in chunk {
+            match *b {
+                b'"' | b'\\' => { s.push('\\'); s.push(*b as char); },
+                0x20..=0x7e => s.push(*b as char),
+                _ => match escape {
+                    Escape::Decimal => s.push_str(&format!("\\{:03}", b)),
+                    Escape::Octal => s.push_str(&format!("\\{:03o}", b))
+                }
+            }
+        }
+        s.push('"');
+        strings.push(s);
+    }
+    strings.join(" ")
+}
+
+// Quote a TXT record for a zone file
+pub fn quote_txt(text: &str) -> String {
+    quote_strings(text, Escape::Decimal)
+}
+
+// Format the RDATA of a single record in zone file presentation format.
+// Hostnames are written out fully qualified so they don't get the origin
+// appended, and text is quoted.  Other formats built on RFC 1035 syntax
+// (e.g. Cloud DNS rrdatas) use this too, so SOAs stay on one line.
+pub fn rdata(rtype: &str, value: &str) -> String {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    match rtype {
+        "CNAME" | "NS" | "PTR" => absolute(value),
+        "TXT" => quote_txt(value),
+        "MX" if fields.len() == 2 => format!("{} {}", fields[0], absolute(fields[1])),
+        "SRV" if fields.len() == 4 => format!("{} {} {} {}", fields[0], fields[1],
+                                              fields[2], absolute(fields[3])),
+        "CAA" if fields.len() >= 3 => {
+            let caa_value = value.splitn(3, ' ').nth(2).unwrap_or("");
+            format!("{} {} {}", fields[0], fields[1], quote_txt(caa_value))
+        },
+        "SOA" if fields.len() == 7 => {
+            // tinydns lets the contact be written as an email address
+            let contact = fields[1].replacen('@', ".", 1);
+            format!("{} {} {}", absolute(fields[0]), absolute(&contact), fields[2..].join(" "))
+        },
+        _ => value.to_string()
+    }
+}
+
+// The SOA gets spread over several lines with its timers labelled
+fn soa_rdata(rec: &TinyDNSRecord) -> String {
+    let fields: Vec<&str> = rec.target.split_whitespace().collect();
+    if fields.len() != 7 {
+        return rec.target.to_string();
+    }
+    let contact = fields[1].replacen('@', ".", 1);
+    format!("{} {} (\n\t\t\t\t{} ; serial\n\t\t\t\t{} ; refresh\n\t\t\t\t{} ; retry\n\t\t\t\t{} ; expire\n\t\t\t\t{} ) ; minimum",
+            absolute(fields[0]), absolute(&contact), fields[2], fields[3],
+            fields[4], fields[5], fields[6])
+}
+
+// Given a Vec of TinyDNSRecords, build one master file per zone.  Zones come
+// from the SOA records ('Z' and '.' lines); only the first SOA seen for a
+// zone is used.  Records that fall outside every zone are skipped with a
+// warning.  Returns (zone name, file contents) pairs.
+pub fn to_zone_files(tdrs: &[TinyDNSRecord]) -> Vec<(String, String)> {
+    let (zones, leftovers) = zone::split_by_zone(tdrs);
+    let mut retval = Vec::new();
+
+    for rec in leftovers {
+        println!("Warning: skipping {} record for {}, it is not in any zone with an SOA",
+                 rec.rtype, rec.fqdn);
+    }
+
+    for (zname, recs) in zones {
+        // Unwrap is safe, the zone only exists because it has an SOA
+        let soa = recs.iter().find(|r| r.rtype == "SOA").unwrap();
+        let mut lines = vec![format!("$ORIGIN {}", absolute(&zname)),
+                             format!("$TTL {}", soa.ttl)];
+
+        // SOA first, then everything else in the order it was given
+        let others = recs.iter().filter(|r| r.rtype != "SOA");
+        for rec in Some(soa).into_iter().chain(others) {
+            let owner = match zone::relative_name(&rec.fqdn, &zname).as_str() {
+                "" => "@".to_string(),
+                x  => x.to_string()
+            };
+            let ttl = match rec.ttl == soa.ttl {
+                true => String::new(),
+                false => rec.ttl.to_string()
+            };
+            let rdata = match rec.rtype.as_str() {
+                "SOA" => soa_rdata(rec),
+                _     => rdata(&rec.rtype, &rec.target)
+            };
+            lines.push(format!("{:<23} {:<7} IN {:<5} {}", owner, ttl, rec.rtype, rdata));
+        }
+        lines.push(String::new());
+        retval.push((zname, lines.join("\n")));
+    }
+
+    retval
+}
+
+// A logical line of a zone file: one directive or resource record, with any
+// parenthesised continuation lines folded in
+struct Entry {
+    line: usize,
+    // True if the line started with whitespace, i.e. the owner is omitted
+    blank_owner: bool,
+    // (text, was it quoted?)
+    tokens: Vec<(String, bool)>
+}
+
+// Split zone file text into Entries, dealing with comments, quoting and
+// parentheses.  Returns an error message with a line number on bad syntax.
+fn tokenize(text: &str) -> Result<Vec<Entry>, String> {
+    let mut retval = Vec::new();
+    let mut tokens: Vec<(String, bool)> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut depth = 0;
+    let mut line = 1;
+    let mut start_line = 1;
+    let mut blank_owner = false;
+    let mut at_line_start = true;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if at_line_start && tokens.is_empty() && current.is_empty() && depth == 0 {
+            blank_owner = c == ' ' || c == '\t';
+            start_line = line;
+        }
+        at_line_start = false;
+
+        if in_quotes {
+            match c {
+                '"' => in_quotes = false,
+                '\\' => {
+                    // Keep escapes as they are; they're decoded per type later
+                    current.push(c);
+                    if let Some(n) = chars.next() {
+                        if n == '\n' { line += 1; }
+                        current.push(n);
+                    }
+                },
+                '\n' => {
+                    return Err(format!("line {}: unterminated quoted string", line));
+                },
+                _ => current.push(c)
+            }
+            continue;
+        }
+
+        match c {
+            '"' => { in_quotes = true; quoted = true; },
+            '\\' => {
+                current.push(c);
+                if let Some(n) = chars.next() {
+                    current.push(n);
+                }
+            },
+            ';' => {
+                // Comment, skip to end of line
+                while let Some(&n) = chars.peek() {
+                    if n == '\n' { break; }
+                    chars.next();
+                }
+            },
+            '(' => {
+                if !current.is_empty() || quoted {
+                    tokens.push((current.split_off(0), quoted));
+                    quoted = false;
+                }
+                depth += 1;
+            },
+            ')' => {
+                if depth == 0 {
+                    return Err(format!("line {}: unbalanced ')'", line));
+                }
+                if !current.is_empty() || quoted {
+                    tokens.push((current.split_off(0), quoted));
+                    quoted = false;
+                }
+                depth -= 1;
+            },
+            ' ' | '\t' | '\r' | '\n' => {
+                if !current.is_empty() || quoted {
+                    tokens.push((current.split_off(0), quoted));
+                    quoted = false;
+                }
+                if c == '\n' {
+                    line += 1;
+                    if depth == 0 {
+                        if !tokens.is_empty() {
+                            retval.push(Entry { line: start_line, blank_owner,
+                                                tokens: tokens.split_off(0) });
+                        }
+                        at_line_start = true;
+                    }
+                }
+            },
+            _ => current.push(c)
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("line {}: unterminated quoted string", line));
+    }
+    if depth != 0 {
+        return Err(format!("line {}: unbalanced '('", start_line));
+    }
+    if !current.is_empty() || quoted {
+        tokens.push((current, quoted));
+    }
+    if !tokens.is_empty() {
+        retval.push(Entry { line: start_line, blank_owner, tokens });
+    }
+    Ok(retval)
+}
+
+// Decode the backslash escapes in a quoted string: \X is a literal X and
+// \DDD is a decimal octet
+fn unescape_text(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 1 < bytes.len() {
+            if i + 3 < bytes.len() && bytes[i + 1..i + 4].iter().all(|b| b.is_ascii_digit()) {
+                let n = bytes[i + 1..i + 4].iter()
+                    .fold(0u32, |acc, b| acc * 10 + u32::from(b - b'0'));
+                if n <= 255 {
+                    out.push(n as u8);
+                    i += 4;
+                    continue;
+                }
+            }
+            out.push(bytes[i + 1]);
+            i += 2;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Parse a TTL, which may be plain seconds or use BIND's unit suffixes
+// (e.g. 1h30m).  Returns None if it isn't a TTL at all.
+fn parse_ttl(text: &str) -> Option<u32> {
+    if text.is_empty() || !text.as_bytes()[0].is_ascii_digit() {
+        return None;
+    }
+    let mut total: i64 = 0;
+    let mut num: i64 = 0;
+    let mut pending = false;
+    for c in text.chars() {
+        if let Some(d) = c.to_digit(10) {
+            // Saturate rather than overflow, the range check below
+            // catches it
+            num = num.saturating_mul(10).saturating_add(i64::from(d));
+            pending = true;
+            continue;
+        }
+        let mult = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return None
+        };
+        if !pending {
+            return None;
+        }
+        total = total.saturating_add(num.saturating_mul(mult));
+        num = 0;
+        pending = false;
+    }
+    total = total.saturating_add(num);
+    if total > i64::from(tinydns::MAX_TTL) {
+        return None;
+    }
+    Some(total as u32)
+}
+
+// Turn a possibly relative name from a zone file into an absolute name
+// without a trailing dot
+fn qualify(name: &str, origin: &str) -> String {
+    if name == "@" {
+        return origin.to_string();
+    }
+    if name.ends_with('.') && !name.ends_with("\\.") {
+        return name.trim_end_matches('.').to_string();
+    }
+    match origin.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", name, origin)
+    }
+}
+
+// Parser state carried across $INCLUDEs
+struct ZoneParser {
+    origin: String,
+    default_ttl: Option<u32>,
+    last_ttl: Option<u32>,
+    // TTL for records when there's no $TTL and none came before
+    fallback_ttl: u32,
+    last_owner: Option<String>,
+    error_flag: bool,
+    records: Vec<TinyDNSRecord>
+}
+
+// Record types we know how to turn into TinyDNSRecords
+const SUPPORTED: &[&str] = &["A", "AAAA", "CNAME", "NS", "PTR", "MX", "TXT", "SOA", "SRV", "CAA"];
+
+impl ZoneParser {
+    // Read one file's worth of entries
+    fn parse_file(&mut self, fname: &str, depth: usize) {
+        if depth > 16 {
+            println!("{}: $INCLUDE nested too deeply", fname);
+            self.error_flag = true;
+            return;
+        }
+        let text = match fs::read_to_string(fname) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("Error opening file {}: {}", fname, e);
+                self.error_flag = true;
+                return;
+            }
+        };
+        let entries = match tokenize(&text) {
+            Ok(x) => x,
+            Err(e) => {
+                println!("{}:{}", fname, e);
+                self.error_flag = true;
+                return;
+            }
+        };
+        for entry in entries {
+            if let Err(e) = self.parse_entry(fname, &entry, depth) {
+                println!("{}:{}: {}", fname, entry.line, e);
+                self.error_flag = true;
+            }
+        }
+    }
+
+    fn parse_entry(&mut self, fname: &str, entry: &Entry, depth: usize) -> Result<(), String> {
+        let mut toks: Vec<(String, bool)> = entry.tokens.clone();
+
+        // Directives first
+        match toks[0].0.to_uppercase().as_str() {
+            "$ORIGIN" if !entry.blank_owner => {
+                let name = toks.get(1).ok_or("$ORIGIN needs a name")?;
+                self.origin = qualify(&name.0, &self.origin);
+                return Ok(());
+            },
+            "$TTL" if !entry.blank_owner => {
+                let ttl = toks.get(1).and_then(|t| parse_ttl(&t.0)).ok_or("$TTL needs a TTL")?;
+                self.default_ttl = Some(ttl);
+                return Ok(());
+            },
+            "$INCLUDE" if !entry.blank_owner => {
+                let file = toks.get(1).ok_or("$INCLUDE needs a file name")?;
+                // Relative paths are taken from the including file's directory
+                let path = match Path::new(fname).parent() {
+                    Some(dir) if !Path::new(&file.0).is_absolute() => dir.join(&file.0),
+                    _ => Path::new(&file.0).to_path_buf()
+                };
+                let saved_origin = self.origin.to_string();
+                if let Some(o) = toks.get(2) {
+                    self.origin = qualify(&o.0, &self.origin);
+                }
+                self.parse_file(&path.to_string_lossy(), depth + 1);
+                self.origin = saved_origin;
+                return Ok(());
+            },
+            x if x.starts_with('$') && !entry.blank_owner => {
+                return Err(format!("unsupported directive {}", toks[0].0));
+            },
+            _ => {}
+        }
+
+        // Owner name, or the previous one if the line started with a blank
+        let owner = match entry.blank_owner {
+            true => self.last_owner.clone().ok_or("no previous owner name to inherit")?,
+            false => qualify(&toks.remove(0).0, &self.origin)
+        };
+
+        // TTL and class can come in either order before the type
+        let mut ttl = None;
+        while !toks.is_empty() {
+            let t = toks[0].0.to_uppercase();
+            if t == "IN" {
+                toks.remove(0);
+            } else if t == "CH" || t == "HS" {
+                return Err(format!("unsupported class {}", t));
+            } else if let Some(x) = parse_ttl(&t) {
+                ttl = Some(x);
+                toks.remove(0);
+            } else {
+                break;
+            }
+        }
+        if toks.is_empty() {
+            return Err("missing record type".to_string());
+        }
+        let rtype = toks.remove(0).0.to_uppercase();
+        if !SUPPORTED.contains(&rtype.as_str()) {
+            return Err(format!("unsupported record type {}", rtype));
+        }
+
+        let rdata: Vec<String> = toks.iter().map(|t| t.0.to_string()).collect();
+        let target = self.rdata(&rtype, &rdata, &toks)?;
+
+        // Explicit TTL, else $TTL, else the last one seen, else our default
+        let ttl = ttl.or(self.default_ttl).or(self.last_ttl).unwrap_or(self.fallback_ttl);
+        self.last_ttl = Some(ttl);
+        self.last_owner = Some(owner.to_string());
+
+        // Records from an $INCLUDEd file say which file their line is in
+        self.records.push(TinyDNSRecord {
+            rtype,
+            fqdn: owner,
+            target,
+            ttl,
+            line: entry.line,
+            source: match depth {
+                0 => None,
+                _ => Some(fname.to_string())
+            }
+        });
+        Ok(())
+    }
+
+    // Turn the RDATA fields into the target string format TinyDNSRecords use
+    fn rdata(&self, rtype: &str, rdata: &[String], toks: &[(String, bool)])
+             -> Result<String, String> {
+        let want = |n: usize| -> Result<(), String> {
+            match rdata.len() == n {
+                true => Ok(()),
+                false => Err(format!("{} record needs {} field(s), found {}", rtype, n, rdata.len()))
+            }
+        };
+        match rtype {
+            "A" => {
+                want(1)?;
+                rdata[0].parse::<Ipv4Addr>().map_err(|e| format!("{}: {}", rdata[0], e))?;
+                Ok(rdata[0].to_string())
+            },
+            "AAAA" => {
+                want(1)?;
+                rdata[0].parse::<Ipv6Addr>().map_err(|e| format!("{}: {}", rdata[0], e))?;
+                Ok(rdata[0].to_string())
+            },
+            "CNAME" | "NS" | "PTR" => {
+                want(1)?;
+                Ok(qualify(&rdata[0], &self.origin))
+            },
+            "MX" => {
+                want(2)?;
+                rdata[0].parse::<u16>().map_err(|_| format!("bad MX preference {}", rdata[0]))?;
+                Ok(format!("{} {}", rdata[0], qualify(&rdata[1], &self.origin)))
+            },
+            "SRV" => {
+                want(4)?;
+                for f in &rdata[..3] {
+                    f.parse::<u16>().map_err(|_| format!("bad SRV field {}", f))?;
+                }
+                Ok(format!("{} {} {} {}", rdata[0], rdata[1], rdata[2],
+                           qualify(&rdata[3], &self.origin)))
+            },
+            "CAA" => {
+                want(3)?;
+                rdata[0].parse::<u8>().map_err(|_| format!("bad CAA flags {}", rdata[0]))?;
+                Ok(format!("{} {} {}", rdata[0], rdata[1], unescape_text(&rdata[2])))
+            },
+            "TXT" => {
+                if rdata.is_empty() {
+                    return Err("TXT record needs at least one string".to_string());
+                }
+                // Several character-strings are joined back into one
+                Ok(toks.iter().map(|t| unescape_text(&t.0)).collect::<Vec<String>>().concat())
+            },
+            "SOA" => {
+                want(7)?;
+                // The serial is a plain 32-bit number; only the timers are TTLs
+                let serial = rdata[2].parse::<u32>()
+                    .map_err(|_| format!("bad SOA serial {}", rdata[2]))?;
+                let mut fields = vec![serial.to_string()];
+                for f in &rdata[3..] {
+                    fields.push(parse_ttl(f).ok_or(format!("bad SOA field {}", f))?.to_string());
+                }
+                Ok(format!("{} {} {}", qualify(&rdata[0], &self.origin),
+                           qualify(&rdata[1], &self.origin), fields.join(" ")))
+            },
+            _ => Err(format!("unsupported record type {}", rtype))
+        }
+    }
+}
+
+// Given the filename of a BIND master file, read it in and generate a Vec
+// of TinyDNSRecords just as tinydns::from_file would.  'origin' is the
+// starting $ORIGIN, which may be empty if the file sets its own.  Errors are
+// printed with their file and line number, and cause None to be returned
+// once the whole file has been looked at.
+pub fn from_file(fname: &str, origin: &str) -> Option<Vec<TinyDNSRecord>> {
+    from_file_with(fname, origin, tinydns::DEFAULT_TTL)
+}
+
+// As from_file, with the TTL to use before the file sets one
+pub fn from_file_with(fname: &str, origin: &str, default_ttl: u32) -> Option<Vec<TinyDNSRecord>> {
+    let mut parser = ZoneParser {
+        origin: origin.trim_end_matches('.').to_string(),
+        default_ttl: None,
+        last_ttl: None,
+        fallback_ttl: default_ttl,
+        last_owner: None,
+        error_flag: false,
+        records: Vec::new()
+    };
+    parser.parse_file(fname, 0);
+
+    match parser.error_flag {
+        true => None,
+        false => Some(parser.records)
+    }
+}
@@ -0,0 +1,191 @@
+// Define structs and functions for generating Cloudflare-specific Terraform
+// output.  Cloudflare models one resource per value rather than one per
+// record set, so the shared record sets are split back up here.
+use std::collections::HashMap;
+use serde_json;
+use types::{TinyDNSRecord, Route53Record};
+use provider::{self, Provider};
+
+// Cloudflare provider: PTRs go in the reverse zone, everything else in the
+// forward zone.  'proxied' turns on Cloudflare's proxy for the record types
+// that support it.
+pub struct Cloudflare {
+    pub fwdzone: String,
+    pub ptrzone: String,
+    pub proxied: bool
+}
+
+#[derive(Serialize)]
+pub struct CloudflareFile {
+    pub resource: HashMap<String, HashMap<String, CloudflareRecord>>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CloudflareRecord {
+    pub zone_id: String,
+    pub name: String,
+    #[serde(rename="type")]
+    pub rtype: String,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub value: Option<String>,
+    pub ttl: u32,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub priority: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub proxied: Option<bool>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub data: Option<CloudflareData>
+}
+
+// The structured 'data' block SRV and CAA records use instead of 'value'
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct CloudflareData {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub service: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub proto: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub priority: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub weight: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub flags: Option<u8>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub value: Option<String>
+}
+
+impl Cloudflare {
+    pub fn new(fwdzone: &str, ptrzone: &str, proxied: bool) -> Self {
+        Cloudflare {
+            fwdzone: fwdzone.to_string(),
+            ptrzone: ptrzone.to_string(),
+            proxied
+        }
+    }
+}
+
+// 32-bit FNV-1a hash, used to give each value's resource a name that stays
+// the same no matter what else is in the record set
+fn fnv1a(text: &str) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in text.bytes() {
+        hash ^= u32::from(b);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+// Turn a single value from a record set into a CloudflareRecord
+fn record(set: &Route53Record, value: &str, proxied: bool) -> Result<CloudflareRecord, String> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let bad = || format!("Can't parse {} value '{}' for {}", set.rtype, value, set.name);
+    let mut retval = CloudflareRecord {
+        zone_id:  set.zone_id.to_string(),
+        name:     set.name.trim_end_matches('.').to_string(),
+        rtype:    set.rtype.to_string(),
+        value:    None,
+        ttl:      set.ttl,
+        priority: None,
+        proxied:  None,
+        data:     None
+    };
+
+    match set.rtype.as_str() {
+        "MX" => {
+            if fields.len() != 2 {
+                return Err(bad());
+            }
+            retval.priority = Some(fields[0].parse::<u16>().map_err(|_| bad())?);
+            retval.value = Some(fields[1].trim_end_matches('.').to_string());
+        },
+        "SRV" => {
+            // The owner is _service._proto.name
+            let labels: Vec<&str> = retval.name.splitn(3, '.').collect();
+            if fields.len() != 4 || labels.len() != 3 {
+                return Err(bad());
+            }
+            retval.data = Some(CloudflareData {
+                service:  Some(labels[0].to_string()),
+                proto:    Some(labels[1].to_string()),
+                name:     Some(labels[2].to_string()),
+                priority: Some(fields[0].parse::<u16>().map_err(|_| bad())?),
+                weight:   Some(fields[1].parse::<u16>().map_err(|_| bad())?),
+                port:     Some(fields[2].parse::<u16>().map_err(|_| bad())?),
+                target:   Some(fields[3].trim_end_matches('.').to_string()),
+                ..Default::default()
+            });
+        },
+        "CAA" => {
+            if fields.len() < 3 {
+                return Err(bad());
+            }
+            retval.data = Some(CloudflareData {
+                flags: Some(fields[0].parse::<u8>().map_err(|_| bad())?),
+                tag:   Some(fields[1].to_string()),
+                value: Some(value.splitn(3, ' ').nth(2).unwrap_or("").to_string()),
+                ..Default::default()
+            });
+        },
+        "CNAME" | "NS" | "PTR" => {
+            retval.value = Some(value.trim_end_matches('.').to_string());
+        },
+        _ => {
+            retval.value = Some(value.to_string());
+        }
+    }
+
+    // Only address and alias records can go through the proxy, and
+    // proxied records must use Cloudflare's automatic TTL
+    if proxied {
+        if let "A" | "AAAA" | "CNAME" = set.rtype.as_str() {
+            retval.proxied = Some(true);
+            retval.ttl = 1;
+        }
+    }
+
+    Ok(retval)
+}
+
+impl Provider for Cloudflare {
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String {
+        match rec.rtype.as_str() {
+            "PTR" => self.ptrzone.to_string(),
+            _     => self.fwdzone.to_string()
+        }
+    }
+
+    // Split each record set into one cloudflare_record per value.  Resource
+    // names get a hash of the value on the end so adding or removing one
+    // value doesn't rename the others.  Cloudflare manages SOA and apex NS
+    // records itself, so those are skipped.
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+        let apexes = provider::zone_apexes(&sets);
+        let mut resources = HashMap::new();
+
+        for set in sets.values() {
+            if provider::service_managed(set, &apexes) {
+                println!("Warning: skipping {} record for {}, Cloudflare manages it",
+                         set.rtype, set.name);
+                continue;
+            }
+            for value in &set.records {
+                let key = format!("{}-{:08x}", provider::resource_name(&set.rtype, &set.name),
+                                  fnv1a(value));
+                resources.insert(key, record(set, value, self.proxied)?);
+            }
+        }
+
+        let mut outer_hash = HashMap::new();
+        outer_hash.insert("cloudflare_record".to_string(), resources);
+        let cf_file = CloudflareFile { resource: outer_hash };
+        serde_json::to_string_pretty(&cf_file).map_err(|e| e.to_string())
+    }
+}
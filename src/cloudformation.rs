@@ -0,0 +1,168 @@
+// Define structs and functions for generating a CloudFormation template
+// from the same record sets Route53's Terraform output is built from.
+// Each hosted zone gets an AWS::Route53::RecordSetGroup, with the zone ID
+// passed in as a template parameter.
+use std::collections::{BTreeMap, HashMap};
+use serde_json;
+use serde_yaml;
+use types::{TinyDNSRecord, Route53Record};
+use provider::{self, Provider};
+use zone::absolute;
+use bind;
+
+// Logical names for the forward and reverse zones within the template
+const FORWARD: &str = "Forward";
+const REVERSE: &str = "Reverse";
+
+// CloudFormation provider: PTRs go in the reverse zone, everything else in
+// the forward zone.  The zone IDs become the parameters' defaults, and the
+// template is written as YAML rather than JSON if 'yaml' is set.
+pub struct CloudFormation {
+    pub fwdzone: String,
+    pub ptrzone: String,
+    pub yaml: bool
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Template {
+    #[serde(rename="AWSTemplateFormatVersion")]
+    pub version: String,
+    #[serde(rename="Description")]
+    pub description: String,
+    #[serde(rename="Parameters")]
+    pub parameters: BTreeMap<String, Parameter>,
+    #[serde(rename="Resources")]
+    pub resources: BTreeMap<String, RecordSetGroup>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct Parameter {
+    #[serde(rename="Type")]
+    pub ptype: String,
+    pub default: String,
+    pub description: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct RecordSetGroup {
+    #[serde(rename="Type")]
+    pub rtype: String,
+    pub properties: RecordSetGroupProperties
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct RecordSetGroupProperties {
+    pub hosted_zone_id: Ref,
+    pub record_sets: Vec<RecordSet>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Ref {
+    #[serde(rename="Ref")]
+    pub name: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct RecordSet {
+    pub name: String,
+    #[serde(rename="Type")]
+    pub rtype: String,
+    #[serde(rename="TTL")]
+    pub ttl: String,
+    pub resource_records: Vec<String>
+}
+
+impl CloudFormation {
+    pub fn new(fwdzone: &str, ptrzone: &str, yaml: bool) -> Self {
+        CloudFormation {
+            fwdzone: fwdzone.to_string(),
+            ptrzone: ptrzone.to_string(),
+            yaml
+        }
+    }
+
+    // Build the template itself from grouped record sets
+    pub fn template(&self, sets: &HashMap<String, Route53Record>) -> Template {
+        let apexes = provider::zone_apexes(sets);
+        let mut groups: BTreeMap<String, Vec<RecordSet>> = BTreeMap::new();
+
+        for set in sets.values() {
+            // The hosted zone already has these, and CloudFormation would
+            // fail trying to create them again
+            if provider::service_managed(set, &apexes) {
+                println!("Warning: skipping {} record for {}, Route53 manages it",
+                         set.rtype, set.name);
+                continue;
+            }
+            groups.entry(set.zone_id.to_string()).or_default().push(RecordSet {
+                name:             absolute(&set.name),
+                rtype:            set.rtype.to_string(),
+                ttl:              set.ttl.to_string(),
+                resource_records: set.records.iter().map(|v| bind::rdata(&set.rtype, v)).collect()
+            });
+        }
+
+        let mut parameters = BTreeMap::new();
+        let mut resources = BTreeMap::new();
+        for (zone, mut record_sets) in groups {
+            record_sets.sort_by(|a, b| (&a.name, &a.rtype).cmp(&(&b.name, &b.rtype)));
+            let param = format!("{}HostedZoneId", zone);
+            parameters.insert(param.to_string(), Parameter {
+                ptype:       "AWS::Route53::HostedZone::Id".to_string(),
+                default:     match zone.as_str() {
+                    REVERSE => self.ptrzone.to_string(),
+                    _       => self.fwdzone.to_string()
+                },
+                description: format!("Hosted zone for {} records", zone.to_lowercase())
+            });
+            resources.insert(format!("{}RecordSetGroup", zone), RecordSetGroup {
+                rtype:      "AWS::Route53::RecordSetGroup".to_string(),
+                properties: RecordSetGroupProperties {
+                    hosted_zone_id: Ref { name: param },
+                    record_sets
+                }
+            });
+        }
+
+        Template {
+            version:     "2010-09-09".to_string(),
+            description: "DNS records generated by tiny2terra".to_string(),
+            parameters,
+            resources
+        }
+    }
+}
+
+impl Provider for CloudFormation {
+    // Records are grouped under the zone's logical name, which the
+    // parameter and resource names are built from
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String {
+        match rec.rtype.as_str() {
+            "PTR" => REVERSE.to_string(),
+            _     => FORWARD.to_string()
+        }
+    }
+
+    fn output_dir(&self) -> &'static str {
+        "cloudformation"
+    }
+
+    fn output_name(&self, basename: &str) -> String {
+        match self.yaml {
+            true => format!("{}.yaml", basename),
+            false => format!("{}.json", basename)
+        }
+    }
+
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+        let template = self.template(&sets);
+        match self.yaml {
+            true => serde_yaml::to_string(&template).map_err(|e| e.to_string()),
+            false => serde_json::to_string_pretty(&template).map_err(|e| e.to_string())
+        }
+    }
+}
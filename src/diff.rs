@@ -0,0 +1,193 @@
+// Define structs and functions for comparing two sets of Route53Records,
+// e.g. freshly generated ones against a previously written Terraform file.
+use std::collections::HashMap;
+use types::Route53Record;
+
+// A record set present on both sides whose TTL and/or values differ
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RecordSetChange {
+    pub name: String,
+    #[serde(rename="type")]
+    pub rtype: String,
+    pub old_ttl: u32,
+    pub new_ttl: u32,
+    pub added: Vec<String>,
+    pub removed: Vec<String>
+}
+
+// Everything that differs between the old and new record sets
+#[derive(Serialize, Debug, Default)]
+pub struct RecordDiff {
+    pub added: Vec<Route53Record>,
+    pub removed: Vec<Route53Record>,
+    pub modified: Vec<RecordSetChange>
+}
+
+impl RecordDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    // Render the differences in a diff-like form for humans:
+    // '+' for new record sets, '-' for removed ones, '~' for changed ones
+    // followed by the individual TTL and value changes.
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        for r in &self.added {
+            lines.push(format!("+ {} {} (ttl {}): {}", r.rtype, r.name, r.ttl,
+                               sorted(&r.records).join(", ")));
+        }
+        for r in &self.removed {
+            lines.push(format!("- {} {} (ttl {}): {}", r.rtype, r.name, r.ttl,
+                               sorted(&r.records).join(", ")));
+        }
+        for c in &self.modified {
+            lines.push(format!("~ {} {}", c.rtype, c.name));
+            if c.old_ttl != c.new_ttl {
+                lines.push(format!("    ttl {} -> {}", c.old_ttl, c.new_ttl));
+            }
+            for v in &c.added {
+                lines.push(format!("    + {}", v));
+            }
+            for v in &c.removed {
+                lines.push(format!("    - {}", v));
+            }
+        }
+        lines.push(format!("{} added, {} removed, {} modified",
+                           self.added.len(), self.removed.len(), self.modified.len()));
+        lines.join("\n")
+    }
+}
+
+// Return a sorted copy of a records vector
+fn sorted(records: &[String]) -> Vec<String> {
+    let mut v = records.to_vec();
+    v.sort();
+    v
+}
+
+// Given the old and new HashMaps of Route53Records (keyed by resource name,
+// as route53::generate produces them), work out which record sets were
+// added, removed or modified.  Zone IDs aren't compared, only the name,
+// type, TTL and values.  Results are sorted by name and then type.
+pub fn diff(old: &HashMap<String, Route53Record>, new: &HashMap<String, Route53Record>)
+            -> RecordDiff {
+    let mut retval = RecordDiff::default();
+
+    for (key, n) in new {
+        match old.get(key) {
+            None => retval.added.push(n.clone()),
+            Some(o) => {
+                let old_values = sorted(&o.records);
+                let new_values = sorted(&n.records);
+                if o.ttl == n.ttl && old_values == new_values {
+                    continue;
+                }
+                retval.modified.push(RecordSetChange {
+                    name:    n.name.to_string(),
+                    rtype:   n.rtype.to_string(),
+                    old_ttl: o.ttl,
+                    new_ttl: n.ttl,
+                    added:   new_values.iter().filter(|v| !old_values.contains(v))
+                                       .cloned().collect(),
+                    removed: old_values.iter().filter(|v| !new_values.contains(v))
+                                       .cloned().collect()
+                });
+            }
+        }
+    }
+
+    for (key, o) in old {
+        if !new.contains_key(key) {
+            retval.removed.push(o.clone());
+        }
+    }
+
+    retval.added.sort_by(|a, b| (&a.name, &a.rtype).cmp(&(&b.name, &b.rtype)));
+    retval.removed.sort_by(|a, b| (&a.name, &a.rtype).cmp(&(&b.name, &b.rtype)));
+    retval.modified.sort_by(|a, b| (&a.name, &a.rtype).cmp(&(&b.name, &b.rtype)));
+    retval
+}
+
+// What a tinydns data file and a live Route53 zone disagree on
+#[derive(Serialize, Debug, Default)]
+pub struct Comparison {
+    pub only_in_route53: Vec<Route53Record>,
+    pub only_in_tinydns: Vec<Route53Record>,
+    // In these the 'old' side is Route53 and the 'new' side is tinydns
+    pub different: Vec<RecordSetChange>,
+    // Route53 record sets that couldn't be compared (aliases and the like)
+    pub skipped: Vec<String>
+}
+
+impl Comparison {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_route53.is_empty() && self.only_in_tinydns.is_empty() &&
+            self.different.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        for r in &self.only_in_route53 {
+            lines.push(format!("Only in Route53: {} {} (ttl {}): {}", r.rtype, r.name,
+                               r.ttl, sorted(&r.records).join(", ")));
+        }
+        for r in &self.only_in_tinydns {
+            lines.push(format!("Only in tinydns: {} {} (ttl {}): {}", r.rtype, r.name,
+                               r.ttl, sorted(&r.records).join(", ")));
+        }
+        for c in &self.different {
+            lines.push(format!("Different: {} {}", c.rtype, c.name));
+            if c.old_ttl != c.new_ttl {
+                lines.push(format!("    ttl {} in Route53, {} in tinydns", c.old_ttl, c.new_ttl));
+            }
+            for v in &c.added {
+                lines.push(format!("    only in tinydns: {}", v));
+            }
+            for v in &c.removed {
+                lines.push(format!("    only in Route53: {}", v));
+            }
+        }
+        for s in &self.skipped {
+            lines.push(format!("Skipped: {}", s));
+        }
+        lines.push(format!("{} only in Route53, {} only in tinydns, {} different, {} skipped",
+                           self.only_in_route53.len(), self.only_in_tinydns.len(),
+                           self.different.len(), self.skipped.len()));
+        lines.join("\n")
+    }
+}
+
+// Put a HashMap of Route53Records into a form where the two sources can be
+// compared: SOA records are dropped (Route53 manages its own), zone IDs are
+// cleared and names and hostname targets lose any trailing dot.
+fn normalise(records: &HashMap<String, Route53Record>) -> HashMap<String, Route53Record> {
+    let mut retval = HashMap::new();
+    for (key, r) in records {
+        if r.rtype == "SOA" {
+            continue;
+        }
+        let mut n = r.clone();
+        n.zone_id = String::new();
+        n.name = n.name.trim_end_matches('.').to_string();
+        if n.rtype != "TXT" {
+            n.records = n.records.iter().map(|v| v.trim_end_matches('.').to_string()).collect();
+        }
+        retval.insert(key.to_string(), n);
+    }
+    retval
+}
+
+// Compare record sets generated from tinydns data with those exported from
+// Route53.  'skipped' lists the Route53 sets that couldn't be converted.
+pub fn compare(tinydns: &HashMap<String, Route53Record>,
+               route53: &HashMap<String, Route53Record>,
+               skipped: Vec<String>) -> Comparison {
+    let d = diff(&normalise(route53), &normalise(tinydns));
+    Comparison {
+        only_in_route53: d.removed,
+        only_in_tinydns: d.added,
+        different:       d.modified,
+        skipped
+    }
+}
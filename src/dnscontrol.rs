@@ -0,0 +1,109 @@
+// Define functions for writing TinyDNSRecords out as a DNSControl
+// dnsconfig.js, with a D() declaration per zone and labels relative to it.
+use types::TinyDNSRecord;
+use zone::{self, absolute};
+
+// Quote a string for JavaScript source
+pub fn js_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => { out.push('\\'); out.push(c); },
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            },
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+// The arguments for a record's DNSControl function after its label, or
+// None if DNSControl has no way to write it
+fn arguments(rec: &TinyDNSRecord) -> Option<Vec<String>> {
+    let fields: Vec<&str> = rec.target.split_whitespace().collect();
+    match rec.rtype.as_str() {
+        "A" | "AAAA" => Some(vec![js_string(&rec.target)]),
+        "CNAME" | "NS" | "PTR" => Some(vec![js_string(&absolute(&rec.target))]),
+        "TXT" => Some(vec![js_string(&rec.target)]),
+        "MX" if fields.len() == 2 => {
+            Some(vec![fields[0].to_string(), js_string(&absolute(fields[1]))])
+        },
+        "SRV" if fields.len() == 4 => {
+            Some(vec![fields[0].to_string(), fields[1].to_string(), fields[2].to_string(),
+                      js_string(&absolute(fields[3]))])
+        },
+        "CAA" if fields.len() >= 3 => {
+            let caa_value = rec.target.splitn(3, ' ').nth(2).unwrap_or("");
+            let mut args = vec![js_string(fields[1]), js_string(caa_value)];
+            // The critical flag is the only one DNSControl knows about
+            match fields[0] {
+                "0" => {},
+                "128" => args.push("CAA_CRITICAL".to_string()),
+                _ => return None
+            }
+            Some(args)
+        },
+        _ => None
+    }
+}
+
+// Given a Vec of TinyDNSRecords and the name of the DNS provider (as in
+// creds.json), produce a dnsconfig.js with a D() per zone with an SOA, or
+// None if there are no zones.  Each zone's DefaultTTL is its SOA's TTL and
+// records with another TTL get a TTL() of their own.  DNSControl manages
+// the SOA, so it isn't written; records outside every zone or that
+// DNSControl can't express are skipped with a warning.
+pub fn to_dnsconfig(tdrs: &[TinyDNSRecord], provider: &str) -> Option<String> {
+    let (zones, leftovers) = zone::split_by_zone(tdrs);
+    if zones.is_empty() {
+        return None;
+    }
+
+    for rec in leftovers {
+        println!("Warning: skipping {} record for {}, it is not in any zone with an SOA",
+                 rec.rtype, rec.fqdn);
+    }
+
+    let mut lines = vec!["var REG_NONE = NewRegistrar(\"none\");".to_string(),
+                         format!("var DSP = NewDnsProvider({});", js_string(provider))];
+
+    for (zname, recs) in zones {
+        // Unwrap is safe, the zone only exists because it has an SOA
+        let soa = recs.iter().find(|r| r.rtype == "SOA").unwrap();
+        lines.push(String::new());
+        let mut items = vec![format!("DefaultTTL({})", soa.ttl)];
+
+        for rec in recs.iter().filter(|r| r.rtype != "SOA") {
+            let mut args = match arguments(rec) {
+                Some(x) => x,
+                None => {
+                    println!("Warning: skipping {} record for {}, DNSControl can't express {}",
+                             rec.rtype, rec.fqdn, rec.target);
+                    continue;
+                }
+            };
+            let label = match zone::relative_name(&rec.fqdn, &zname).as_str() {
+                "" => "@".to_string(),
+                x  => x.to_string()
+            };
+            args.insert(0, js_string(&label));
+            if rec.ttl != soa.ttl {
+                args.push(format!("TTL({})", rec.ttl));
+            }
+            items.push(format!("{}({})", rec.rtype, args.join(", ")));
+        }
+
+        // No trailing comma, older DNSControl versions choke on them
+        lines.push(format!("D({}, REG_NONE, DnsProvider(DSP),", js_string(&zname)));
+        lines.push(format!("    {}", items.join(",\n    ")));
+        lines.push(");".to_string());
+    }
+
+    lines.push(String::new());
+    Some(lines.join("\n"))
+}
@@ -0,0 +1,78 @@
+// Define structs and functions for generating Google Cloud DNS-specific
+// Terraform output, built on the same record sets as Route53.
+use std::collections::HashMap;
+use serde_json;
+use types::{TinyDNSRecord, Route53Record};
+use provider::{self, Provider};
+use zone::absolute;
+use bind;
+
+// Google provider: PTRs go in the reverse managed zone, everything else in
+// the forward one.  Zones are referred to by their managed zone name.
+pub struct Google {
+    pub fwdzone: String,
+    pub ptrzone: String
+}
+
+#[derive(Serialize)]
+pub struct GoogleFile {
+    pub resource: HashMap<String, HashMap<String, GoogleRecordSet>>
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GoogleRecordSet {
+    pub managed_zone: String,
+    pub name: String,
+    #[serde(rename="type")]
+    pub rtype: String,
+    pub ttl: u32,
+    pub rrdatas: Vec<String>
+}
+
+impl Google {
+    pub fn new(fwdzone: &str, ptrzone: &str) -> Self {
+        Google {
+            fwdzone: fwdzone.to_string(),
+            ptrzone: ptrzone.to_string()
+        }
+    }
+}
+
+impl Provider for Google {
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String {
+        match rec.rtype.as_str() {
+            "PTR" => self.ptrzone.to_string(),
+            _     => self.fwdzone.to_string()
+        }
+    }
+
+    // Cloud DNS wants fully qualified names with trailing dots everywhere,
+    // both for the record set name and for hostnames inside rrdatas, and
+    // TXT data quoted.  That's zone file presentation format, so the BIND
+    // writer's rdata formatting does the work.  Cloud DNS creates its own
+    // SOA and apex NS records, so those are skipped.
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+        let apexes = provider::zone_apexes(&sets);
+        let mut resources = HashMap::new();
+
+        for (key, set) in &sets {
+            if provider::service_managed(set, &apexes) {
+                println!("Warning: skipping {} record for {}, Cloud DNS manages it",
+                         set.rtype, set.name);
+                continue;
+            }
+            resources.insert(key.to_string(), GoogleRecordSet {
+                managed_zone: set.zone_id.to_string(),
+                name:         absolute(&set.name),
+                rtype:        set.rtype.to_string(),
+                ttl:          set.ttl,
+                rrdatas:      set.records.iter().map(|v| bind::rdata(&set.rtype, v)).collect()
+            });
+        }
+
+        let mut outer_hash = HashMap::new();
+        outer_hash.insert("google_dns_record_set".to_string(), resources);
+        let g_file = GoogleFile { resource: outer_hash };
+        serde_json::to_string_pretty(&g_file).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,68 @@
+// Define functions for internationalised domain names: names written in
+// Unicode in the input are converted to their ASCII (punycode) form under
+// UTS 46 before anything is validated or written.
+use std::collections::BTreeMap;
+use idna;
+use types::TinyDNSRecord;
+
+// Convert a name to its ASCII form, keeping any trailing dot.  Names that
+// are ASCII already are left exactly as they are.
+pub fn to_ascii(name: &str) -> Result<String, String> {
+    if name.is_ascii() {
+        return Ok(name.to_string());
+    }
+    let (bare, dot) = match name.strip_suffix('.') {
+        Some(x) => (x, "."),
+        None => (name, "")
+    };
+    match idna::domain_to_ascii(bare) {
+        Ok(x) => Ok(format!("{}{}", x, dot)),
+        Err(_) => Err(format!("{} isn't a valid internationalised name", name))
+    }
+}
+
+// The Unicode form of a name with punycode labels in it, if it has any
+// that decode cleanly
+pub fn to_unicode(name: &str) -> Option<String> {
+    let name = name.trim_end_matches('.');
+    if !name.split('.').any(|l| l.to_ascii_lowercase().starts_with("xn--")) {
+        return None;
+    }
+    match idna::domain_to_unicode(name) {
+        (x, Ok(())) => Some(x),
+        (_, Err(_)) => None
+    }
+}
+
+// Convert every Unicode name in 'tdrs', both record names and the
+// hostnames in targets, and return a map of ASCII names to their Unicode
+// forms.  Names that can't be converted are left alone for
+// validation to report.
+pub fn to_ascii_records(tdrs: &mut [TinyDNSRecord]) -> BTreeMap<String, String> {
+    let mut retval = BTreeMap::new();
+    let mut convert = |name: &str| match to_ascii(name) {
+        Ok(ref x) if x != name => {
+            // Keyed and valued by the normalised forms, however the name
+            // was written
+            let ascii = x.trim_end_matches('.').to_string();
+            let unicode = to_unicode(&ascii).unwrap_or_else(|| name.trim_end_matches('.').to_string());
+            retval.insert(ascii, unicode);
+            x.to_string()
+        },
+        _ => name.to_string()
+    };
+
+    for rec in tdrs.iter_mut() {
+        rec.fqdn = convert(&rec.fqdn);
+        // Hostnames only ever appear as whole fields of these targets, and
+        // the other fields are numbers or email-style SOA contacts
+        if let "CNAME" | "NS" | "PTR" | "MX" | "SRV" | "SOA" = rec.rtype.as_str() {
+            if !rec.target.is_ascii() {
+                let fields: Vec<String> = rec.target.split(' ').map(&mut convert).collect();
+                rec.target = fields.join(" ");
+            }
+        }
+    }
+
+    retval
+}
@@ -1,17 +1,61 @@
 #[macro_use] extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
+#[cfg(test)] #[macro_use] extern crate clap;
+extern crate serde_yaml;
+extern crate ureq;
+extern crate hmac;
+extern crate sha2;
+extern crate hex;
+extern crate roxmltree;
+extern crate idna;
+#[cfg(test)] extern crate tiny_http;
 
 pub mod types;
+pub mod provider;
 pub mod route53;
+pub mod cloudflare;
+pub mod google;
+pub mod azure;
+pub mod cloudformation;
 pub mod tinydns;
+pub mod diff;
+pub mod route53api;
+pub mod route53client;
+pub mod zone;
+pub mod bind;
+pub mod octodns;
+pub mod dnscontrol;
+pub mod validate;
+pub mod reverse;
+pub mod idn;
+pub mod ttl;
 
 // Gettin' testy with it
 #[cfg(test)]
 mod tests {
     use types::*;
     use std::collections::HashMap;
-    use route53_parser;
+    use serde_json;
+    use serde_yaml;
+    use route53;
+    use tinydns;
+    use diff;
+    use route53api;
+    use route53client;
+    use zone;
+    use bind;
+    use octodns;
+    use dnscontrol;
+    use validate;
+    use reverse;
+    use idn;
+    use ttl;
+    use provider::{self, Provider};
+    use cloudflare;
+    use google;
+    use azure;
+    use cloudformation;
 
     // Make sure the 'new' function properly converts the &strs sent to it 
     // into Strings, and the record &str into a Vec<String>.
@@ -72,81 +116,1124 @@ mod tests {
         assert_ne!(x, a);
     }
 
-    // route53_parser tests
-    // Ensure parse_line turns a valid line into a valid Route53Record
+    // Parser tests
+    // Ensure a valid line turns into a valid Route53Record
     #[test]
     fn test_r53_parse_line() {
         let line = "+test.foo.com:1.2.3.4:450";
-        let zone = "${aws_route53_zone.zone.zone_id}";
-        let x = Route53Record::new(&zone, "test.foo.com", "A", "1.2.3.4", 450);
-        let y = route53_parser::parse_line(&line, "zone").unwrap();
-        assert_eq!(x, y);
+        let x = Route53Record::new("zone", "test.foo.com", "A", "1.2.3.4", 450);
+        let tdrs = tinydns::parse_line(line);
+        let y = route53::generate("zone", "rzone", &tdrs).unwrap();
+        assert_eq!(&x, y.get("a-test-foo-com").unwrap());
     }
 
-    // Ensure a return of None on unsupported prefix
+    // Ensure an empty result on unsupported prefix
     #[test]
     fn test_r53_parse_line_bad_prefix() {
-        let x = route53_parser::parse_line("@foo.com:1.2.3.4:123", "zone");
-        assert_eq!(x.is_none(), true);
+        let x = tinydns::parse_line("%foo.com:1.2.3.4:123");
+        assert!(x.is_empty());
     }
 
-    // Ensure a return of None on invalid line (doesn't split on : into 3)
+    // Ensure an empty result on invalid line (doesn't split on : into 2)
     #[test]
     fn test_r53_parse_line_bad_line() {
-        let x = route53_parser::parse_line("foo", "zone");
-        assert_eq!(x.is_none(), true);
+        let x = tinydns::parse_line("+foo");
+        assert!(x.is_empty());
     }
 
     // Ensure non-integer TTL is ignored and replaced by 300
     #[test]
     fn test_r53_parse_line_bad_ttl() {
         let line = "+test.foo.com:1.2.3.4:foo";
-        let y = route53_parser::parse_line(&line, "zone").unwrap();
-        assert_eq!(300, y.ttl);
+        let y = tinydns::parse_line(line);
+        assert_eq!(300, y[0].ttl);
+    }
+
+    // Strict parsing rejects bad and out-of-range numbers instead of
+    // defaulting them, but still defaults fields that are left out
+    #[test]
+    fn test_parse_line_strict() {
+        let strict = tinydns::ParseOptions { strict: true, ..Default::default() };
+        for line in &["+test.foo.com:1.2.3.4:foo", "+test.foo.com:1.2.3.4:-300",
+                      "+test.foo.com:1.2.3.4:2147483648", "=test.foo.com:1.2.3.4:86400x",
+                      "@foo.com:1.2.3.4:mx:65536", "@foo.com:1.2.3.4:mx:10:x",
+                      "&foo.com::ns.foo.com:1e3", ".foo.com::ns.foo.com:3.5",
+                      "Zfoo.com:ns.foo.com:hm.foo.com:4294967296",
+                      "Zfoo.com:ns.foo.com:hm.foo.com:1:16384:2048:1048576:-1"] {
+            assert!(tinydns::parse_line_with(line, &strict).is_empty(), "{}", line);
+            assert!(!tinydns::parse_line(line).is_empty(), "{}", line);
+        }
+        assert_eq!(tinydns::parse_line("+test.foo.com:1.2.3.4:-300")[0].ttl, 300);
+
+        let y = tinydns::parse_line_with("+test.foo.com:1.2.3.4:2147483647", &strict);
+        assert_eq!(y[0].ttl, tinydns::MAX_TTL);
+        let y = tinydns::parse_line_with("@foo.com::mx.foo.com:65535", &strict);
+        assert_eq!((y[0].target.as_str(), y[0].ttl), ("65535 mx.foo.com", 300));
+        let y = tinydns::parse_line_with("Zfoo.com:ns.foo.com:hm.foo.com:4294967295::::60", &strict);
+        assert_eq!(y[0].target, "ns.foo.com hm.foo.com 4294967295 16384 2048 1048576 60");
     }
 
-    // Make sure the 4 accepted prefixes are all accepted
+    // Make sure the 4 basic prefixes are all accepted
     #[test]
     fn test_r53_parse_line_check_prefixes() {
-        let a   = route53_parser::parse_line("+test.foo.com:1.2.3.4:300", "z");
-        let ptr = route53_parser::parse_line("^4.3.2.1.in-addr.arpa:foo:300", "z");
-        let cn  = route53_parser::parse_line("Cbar:foo:300", "z");
-        let txt = route53_parser::parse_line("'baz:string:300", "z");
-        assert_eq!(a.is_some(), true);
-        assert_eq!(ptr.is_some(), true);
-        assert_eq!(cn.is_some(), true);
-        assert_eq!(txt.is_some(), true);
+        let a   = tinydns::parse_line("+test.foo.com:1.2.3.4:300");
+        let ptr = tinydns::parse_line("^4.3.2.1.in-addr.arpa:foo:300");
+        let cn  = tinydns::parse_line("Cbar:foo:300");
+        let txt = tinydns::parse_line("'baz:string:300");
+        assert!(!a.is_empty());
+        assert!(!ptr.is_empty());
+        assert!(!cn.is_empty());
+        assert!(!txt.is_empty());
     }
 
     // Ensure a bad/missing filename returns a None
     #[test]
     fn test_r53_parse_parse_bad_file() {
-        let x = route53_parser::parse("foo", "foo");
-        assert_eq!(x.is_none(), true);
+        let x = tinydns::from_file("foo");
+        assert!(x.is_none());
     }
 
     // Ensure a file with bad lines returns None
     #[test]
     fn test_r53_parse_parse_bad_line() {
-        let x = route53_parser::parse("baddata", "foo");
-        assert_eq!(x.is_none(), true);
+        let x = tinydns::from_file("baddata");
+        assert!(x.is_none());
     }
 
     // Ensure a good file parses and matches known good hashmap
     #[test]
     fn test_r53_parse_parse_good_file() {
-        let zone = "${{aws_route53_zone.foo.zone_id}}";
-        let a = Route53Record::new(&zone, "foo.example.com", "A", "1.2.3.4", 600);
-        let b = Route53Record::new(&zone, "4.3.2.1.in-addr.arpa", "PTR", "foo.example.com", 600);
-        let c = Route53Record::new(&zone, "bar.example.com", "CNAME", "foo.example.com", 600);
-        let d = Route53Record::new(&zone, "txt.example.com", "TXT", "Some text string", 600);
+        let zone = "foo";
+        let a = Route53Record::new(zone, "foo.example.com", "A", "1.2.3.4", 600);
+        let b = Route53Record::new(zone, "4.3.2.1.in-addr.arpa", "PTR", "foo.example.com", 600);
+        let c = Route53Record::new(zone, "bar.example.com", "CNAME", "foo.example.com", 600);
+        let d = Route53Record::new(zone, "txt.example.com", "TXT", "Some text string", 600);
         let mut good_hash = HashMap::new();
         good_hash.insert("a-foo-example-com".to_string(), a);
         good_hash.insert("ptr-4-3-2-1-in-addr-arpa".to_string(), b);
         good_hash.insert("cname-bar-example-com".to_string(), c);
         good_hash.insert("txt-txt-example-com".to_string(), d);
-        let x = route53_parser::parse("testdata", "foo").unwrap();
-        assert_eq!(good_hash.len(), x.len());
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let x = route53::generate(zone, zone, &tdrs).unwrap();
+        assert_eq!(good_hash, x);
+    }
+
+    // Reverse conversion tests
+    // Make sure A+PTR pairs collapse back into '=' and MX+A into '@'
+    #[test]
+    fn test_to_data_collapses_pairs() {
+        let mut tdrs = tinydns::parse_line("=foo.example.com:10.0.0.1:900");
+        tdrs.append(&mut tinydns::parse_line("@example.com:10.0.0.2:mail.example.com:10:900"));
+        tdrs.append(&mut tinydns::parse_line("&example.com::ns1.example.com:3600"));
+        let lines = tinydns::to_data(&tdrs);
+        assert_eq!(lines, vec!["=foo.example.com:10.0.0.1:900",
+                               "@example.com:10.0.0.2:mail.example.com:10:900",
+                               "&example.com::ns1.example.com:3600"]);
+    }
+
+    // A PTR with a different TTL or name than its A can't share a '=' line
+    #[test]
+    fn test_to_data_mismatched_ptr() {
+        let mut tdrs = tinydns::parse_line("+foo.example.com:10.0.0.1:900");
+        tdrs.append(&mut tinydns::parse_line("^1.0.0.10.in-addr.arpa:bar.example.com:900"));
+        let lines = tinydns::to_data(&tdrs);
+        assert_eq!(lines, vec!["+foo.example.com:10.0.0.1:900",
+                               "^1.0.0.10.in-addr.arpa:bar.example.com:900"]);
+    }
+
+    // TXT data with colons has to be escaped on the way out and decoded
+    // on the way back in
+    #[test]
+    fn test_to_data_txt_escape() {
+        let tdrs = vec![TinyDNSRecord {
+            rtype:  "TXT".to_string(),
+            fqdn:   "txt.example.com".to_string(),
+            target: "v=spf1 a:mail.example.com -all".to_string(),
+            ttl:    300,
+            line:   0,
+            source: None }];
+        let lines = tinydns::to_data(&tdrs);
+        assert_eq!(lines[0], "'txt.example.com:v=spf1 a\\072mail.example.com -all:300");
+        assert_eq!(tinydns::parse_line(&lines[0]), tdrs);
+    }
+
+    // Generating Terraform and converting it back should give the same
+    // records we started with
+    #[test]
+    fn test_to_tinydns_round_trip() {
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let r53 = route53::generate("foo", "foo", &tdrs).unwrap();
+        let json = serde_json::to_string(&r53).unwrap();
+        let back: HashMap<String, Route53Record> = serde_json::from_str(&json).unwrap();
+        let mut lines = tinydns::to_data(&route53::to_tinydns(&back));
+        lines.sort();
+        assert_eq!(lines, vec!["'txt.example.com:Some text string:600",
+                               "=foo.example.com:1.2.3.4:600",
+                               "Cbar.example.com:foo.example.com:600"]);
+    }
+
+    // Comments and blank lines are skipped rather than treated as errors
+    #[test]
+    fn test_is_ignored() {
+        assert!(tinydns::is_ignored("# a comment"));
+        assert!(tinydns::is_ignored("   "));
+        assert!(!tinydns::is_ignored("+foo.example.com:1.2.3.4"));
+    }
+
+    // Diff tests
+    // Identical inputs shouldn't produce any differences
+    #[test]
+    fn test_diff_identical() {
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let a = route53::generate("foo", "foo", &tdrs).unwrap();
+        let b = route53::generate("foo", "foo", &tdrs).unwrap();
+        assert!(diff::diff(&a, &b).is_empty());
+    }
+
+    // Make sure added, removed and modified record sets are all found, and
+    // that modifications are broken down into TTL and per-value changes
+    #[test]
+    fn test_diff_changes() {
+        let mut old = HashMap::new();
+        old.insert("a-foo".to_string(), Route53Record::new("z", "foo", "A", "1.1.1.1", 300));
+        old.insert("a-bar".to_string(), Route53Record::new("z", "bar", "A", "2.2.2.2", 300));
+        let mut new = HashMap::new();
+        let mut foo = Route53Record::new("z", "foo", "A", "1.1.1.1", 600);
+        foo.add_record("3.3.3.3");
+        new.insert("a-foo".to_string(), foo);
+        new.insert("a-baz".to_string(), Route53Record::new("z", "baz", "A", "4.4.4.4", 300));
+        let d = diff::diff(&old, &new);
+        assert_eq!(d.added.len(), 1);
+        assert_eq!(d.added[0].name, "baz");
+        assert_eq!(d.removed.len(), 1);
+        assert_eq!(d.removed[0].name, "bar");
+        assert_eq!(d.modified, vec![diff::RecordSetChange {
+            name:    "foo".to_string(),
+            rtype:   "A".to_string(),
+            old_ttl: 300,
+            new_ttl: 600,
+            added:   vec!["3.3.3.3".to_string()],
+            removed: Vec::new() }]);
+    }
+
+    // Route53 export tests
+    // Quoted TXT strings should be joined back up and unescaped
+    #[test]
+    fn test_unquote_txt() {
+        assert_eq!(route53api::unquote_txt("\"v=spf1 -all\""), "v=spf1 -all");
+        assert_eq!(route53api::unquote_txt("\"one \" \"two\""), "one two");
+        assert_eq!(route53api::unquote_txt("\"say \\\"hi\\\"\""), "say \"hi\"");
+    }
+
+    // Make sure an export is read in, escapes and aliases are dealt with,
+    // and the comparison against testdata finds each kind of difference
+    #[test]
+    fn test_compare_export() {
+        let sets = route53api::from_file("testdata-route53.json").unwrap();
+        assert_eq!(sets.len(), 6);
+        let (r53, skipped) = route53api::to_route53_records(&sets);
+        assert!(r53.contains_key("a-*-example-com"));
+        assert_eq!(skipped.len(), 1);
+
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let tdns = route53::generate("", "", &tdrs).unwrap();
+        let result = diff::compare(&tdns, &r53, skipped);
+        assert_eq!(result.only_in_route53.len(), 1);
+        assert_eq!(result.only_in_route53[0].name, "*.example.com");
+        assert_eq!(result.only_in_tinydns.len(), 1);
+        assert_eq!(result.only_in_tinydns[0].rtype, "PTR");
+        assert_eq!(result.different.len(), 1);
+        assert_eq!(result.different[0].old_ttl, 300);
+        assert_eq!(result.different[0].new_ttl, 600);
+        assert!(result.different[0].added.is_empty());
+    }
+
+    // Zone and BIND output tests
+    // Names should be placed in the most specific zone and made relative
+    #[test]
+    fn test_zone_relative_names() {
+        let zones = vec!["example.com".to_string(), "sub.example.com".to_string()];
+        assert_eq!(zone::zone_for("a.sub.example.com.", &zones).unwrap(), "sub.example.com");
+        assert_eq!(zone::zone_for("a.example.com", &zones).unwrap(), "example.com");
+        assert!(zone::zone_for("example.org", &zones).is_none());
+        assert_eq!(zone::relative_name("Foo.Example.com.", "example.com"), "Foo");
+        assert_eq!(zone::relative_name("example.com", "example.com"), "");
     }
-}
 
+    // Long TXT data has to be split into 255 octet strings, with quotes and
+    // backslashes escaped
+    #[test]
+    fn test_bind_quote_txt() {
+        assert_eq!(bind::quote_txt("say \"hi\" \\o/"), "\"say \\\"hi\\\" \\\\o/\"");
+        assert_eq!(bind::quote_txt("é"), "\"\\195\\169\"");
+        let long = "x".repeat(300);
+        let quoted = bind::quote_txt(&long);
+        assert_eq!(quoted, format!("\"{}\" \"{}\"", "x".repeat(255), "x".repeat(45)));
+    }
+
+    // Make sure a zone file gets its origin, default TTL, SOA and relative
+    // owner names, and that records outside the zone are left out
+    #[test]
+    fn test_bind_zone_files() {
+        let mut tdrs = tinydns::parse_line("Zexample.com:ns1.example.com:hostmaster.example.com:1:2:3:4:5:3600");
+        tdrs.append(&mut tinydns::parse_line("=foo.example.com:10.0.0.1:900"));
+        tdrs.append(&mut tinydns::parse_line("Cwww.example.com:foo.example.com:3600"));
+        let files = bind::to_zone_files(&tdrs);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "example.com");
+        let lines: Vec<&str> = files[0].1.lines().collect();
+        assert_eq!(lines[0], "$ORIGIN example.com.");
+        assert_eq!(lines[1], "$TTL 3600");
+        assert!(lines[2].starts_with("@                               IN SOA   ns1.example.com. hostmaster.example.com. ("));
+        assert!(files[0].1.contains("foo                     900     IN A     10.0.0.1\n"));
+        assert!(files[0].1.contains("www                             IN CNAME foo.example.com.\n"));
+        assert!(!files[0].1.contains("PTR"));
+    }
+
+    // Make sure a zone file with directives, relative names, parentheses,
+    // quoting and an include all comes out as the right TinyDNSRecords
+    #[test]
+    fn test_bind_from_file() {
+        let tdrs = bind::from_file("testdata.zone", "").unwrap();
+        let tdr = |rtype: &str, fqdn: &str, target: &str, ttl: u32, line: usize| TinyDNSRecord {
+            rtype: rtype.to_string(), fqdn: fqdn.to_string(),
+            target: target.to_string(), ttl, line, source: None };
+        let included = |rec: TinyDNSRecord| TinyDNSRecord {
+            source: Some("testdata-include.zone".to_string()), ..rec };
+        assert_eq!(tdrs, vec![
+            tdr("SOA", "example.com", "ns1.example.com hostmaster.example.com 2019041101 16384 2048 1048576 2560", 3600, 4),
+            tdr("NS", "example.com", "ns1.example.com", 3600, 10),
+            tdr("MX", "example.com", "10 mail.example.com", 3600, 11),
+            tdr("A", "ns1.example.com", "10.0.0.53", 3600, 12),
+            tdr("A", "foo.example.com", "10.0.0.1", 600, 13),
+            tdr("CNAME", "bar.example.com", "foo.example.com", 3600, 14),
+            tdr("TXT", "txt.example.com", "v=spf1 a;mx \"quoted\" -all", 3600, 15),
+            tdr("SRV", "_sip._tcp.example.com", "10 20 5060 sip.example.com", 300, 16),
+            included(tdr("A", "www.sub.example.com", "10.0.1.1", 3600, 2)),
+            included(tdr("CAA", "sub.example.com", "0 issue letsencrypt.org", 3600, 3)),
+            tdr("A", "after.example.com", "10.0.0.2", 3600, 18)]);
+
+        // Problems with included records name the file their lines are in
+        let mut tdrs = tdrs;
+        tdrs.push(tdr("CNAME", "www.sub.example.com", "foo.example.com", 3600, 19));
+        let problems: Vec<String> = validate::cname_conflicts(&tdrs).iter()
+            .map(|p| p.to_text()).collect();
+        assert_eq!(problems, vec!["line 19; testdata-include.zone line 2: www.sub.example.com: \
+                                   CNAME alongside other records (A)"]);
+    }
+
+    // Bad records, bad syntax and missing includes should all fail the parse
+    #[test]
+    fn test_bind_from_file_errors() {
+        assert!(bind::from_file("foo", "example.com").is_none());
+        assert!(bind::from_file("baddata", "example.com").is_none());
+    }
+
+    // SOA serials are 32-bit numbers, not TTLs: no upper TTL limit and no
+    // units, while the timers after them may still use units
+    #[test]
+    fn test_bind_soa_serial() {
+        let tdrs = bind::from_file("testdata-soa.zone", "").unwrap();
+        assert_eq!(tdrs[0].target,
+                   "ns1.example.net hostmaster.example.net 3000000000 14400 1800 1209600 86400");
+        assert!(bind::from_file("testdata-badserial.zone", "").is_none());
+    }
+
+    // The bind subcommand shares its name with nothing in the global args,
+    // so asking for BIND output must not switch on BIND input
+    #[test]
+    fn test_bind_subcommand_is_not_bind_input() {
+        let yaml = load_yaml!("cli.yml");
+        let app = clap::App::from_yaml(yaml);
+        let matches = app.clone()
+            .get_matches_from_safe(vec!["tiny2terra", "-i", "data", "bind"]).unwrap();
+        assert_eq!(matches.subcommand_name(), Some("bind"));
+        assert!(!matches.is_present("bind_input"));
+        let matches = app
+            .get_matches_from_safe(vec!["tiny2terra", "-i", "db.zone", "-b", "bind"]).unwrap();
+        assert!(matches.is_present("bind_input"));
+    }
+
+    // Provider tests
+    // A provider that puts everything in one zone and names resources by
+    // name only, to check the shared grouping honours both hooks
+    struct OneZone;
+    impl Provider for OneZone {
+        fn zone_for(&self, _rec: &TinyDNSRecord) -> String {
+            "only".to_string()
+        }
+        fn resource_name(&self, _rtype: &str, name: &str) -> String {
+            name.replace(".", "_")
+        }
+        fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+            Ok(format!("{} sets", sets.len()))
+        }
+    }
+
+    #[test]
+    fn test_provider_hooks() {
+        let mut tdrs = tinydns::parse_line("+foo.example.com:1.2.3.4:300");
+        tdrs.append(&mut tinydns::parse_line("+foo.example.com:1.2.3.5:300"));
+        tdrs.append(&mut tinydns::parse_line("^4.3.2.1.in-addr.arpa:foo.example.com:300"));
+        let sets = provider::group(&OneZone, &tdrs).unwrap();
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets["foo_example_com"].records.len(), 2);
+        assert_eq!(sets["4_3_2_1_in-addr_arpa"].zone_id, "only");
+        assert_eq!(OneZone.generate(&tdrs).unwrap(), "2 sets");
+    }
+
+    // The Route53 provider should produce the same Terraform JSON the
+    // route53 subcommand always has
+    #[test]
+    fn test_route53_provider_serialise() {
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let json = route53::Route53::new("fwd", "rev").generate(&tdrs).unwrap();
+        let file: Route53File = serde_json::from_str(&json).unwrap();
+        let recs = &file.resource["aws_route53_record"];
+        assert_eq!(recs.len(), 4);
+        assert_eq!(recs["ptr-4-3-2-1-in-addr-arpa"].zone_id, "rev");
+        assert_eq!(recs["a-foo-example-com"].zone_id, "fwd");
+    }
+
+    // Cloudflare tests
+    // Record sets should be split into one resource per value, with names
+    // that don't change when other values come and go
+    #[test]
+    fn test_cloudflare_split_values() {
+        let cf = cloudflare::Cloudflare::new("fwd", "rev", false);
+        let one = tinydns::parse_line("+foo.example.com:1.2.3.4:300");
+        let mut two = one.clone();
+        two.append(&mut tinydns::parse_line("+foo.example.com:1.2.3.5:300"));
+        let a: serde_json::Value = serde_json::from_str(&cf.generate(&one).unwrap()).unwrap();
+        let b: serde_json::Value = serde_json::from_str(&cf.generate(&two).unwrap()).unwrap();
+        let a = a["resource"]["cloudflare_record"].as_object().unwrap().clone();
+        let b = b["resource"]["cloudflare_record"].as_object().unwrap().clone();
+        assert_eq!(a.len(), 1);
+        assert_eq!(b.len(), 2);
+        let key = a.keys().next().unwrap();
+        assert!(key.starts_with("a-foo-example-com-"));
+        assert_eq!(a[key], b[key]);
+    }
+
+    // MX priority and SRV data should be broken out natively, proxying only
+    // applies to A/AAAA/CNAME, and SOA/apex NS records are left alone
+    #[test]
+    fn test_cloudflare_types() {
+        let tdrs = bind::from_file("testdata.zone", "").unwrap();
+        let cf = cloudflare::Cloudflare::new("fwd", "rev", true);
+        let json: serde_json::Value = serde_json::from_str(&cf.generate(&tdrs).unwrap()).unwrap();
+        let recs = json["resource"]["cloudflare_record"].as_object().unwrap();
+        let find = |rtype: &str, name: &str| recs.values()
+            .find(|r| r["type"] == rtype && r["name"] == name).unwrap().clone();
+        let mx = find("MX", "example.com");
+        assert_eq!(mx["priority"], 10);
+        assert_eq!(mx["value"], "mail.example.com");
+        assert!(mx.get("proxied").is_none());
+        let srv = find("SRV", "_sip._tcp.example.com");
+        assert_eq!(srv["data"]["service"], "_sip");
+        assert_eq!(srv["data"]["proto"], "_tcp");
+        assert_eq!(srv["data"]["name"], "example.com");
+        assert_eq!(srv["data"]["port"], 5060);
+        let a = find("A", "foo.example.com");
+        assert_eq!(a["proxied"], true);
+        assert_eq!(a["ttl"], 1);
+        assert!(recs.values().all(|r| r["type"] != "SOA" && r["type"] != "NS"));
+    }
+
+    // Google Cloud DNS tests
+    // Names and hostname targets need trailing dots, TXT needs quoting, and
+    // record sets should still be merged like Route53's
+    #[test]
+    fn test_google_rrdatas() {
+        let tdrs = bind::from_file("testdata.zone", "").unwrap();
+        let g = google::Google::new("fwd", "rev");
+        let json: serde_json::Value = serde_json::from_str(&g.generate(&tdrs).unwrap()).unwrap();
+        let recs = &json["resource"]["google_dns_record_set"];
+        assert_eq!(recs["cname-bar-example-com"]["name"], "bar.example.com.");
+        assert_eq!(recs["cname-bar-example-com"]["rrdatas"][0], "foo.example.com.");
+        assert_eq!(recs["cname-bar-example-com"]["managed_zone"], "fwd");
+        assert_eq!(recs["mx-example-com"]["rrdatas"][0], "10 mail.example.com.");
+        assert_eq!(recs["srv-_sip-_tcp-example-com"]["rrdatas"][0], "10 20 5060 sip.example.com.");
+        assert_eq!(recs["txt-txt-example-com"]["rrdatas"][0], "\"v=spf1 a;mx \\\"quoted\\\" -all\"");
+        assert!(recs.get("soa-example-com").is_none());
+        assert!(recs.get("ns-example-com").is_none());
+    }
+
+    // Targets that already have their trailing dot shouldn't get another
+    #[test]
+    fn test_google_trailing_dots() {
+        let tdrs = tinydns::parse_line("Cbar.example.com.:foo.example.com.:300");
+        let g = google::Google::new("fwd", "rev");
+        let json: serde_json::Value = serde_json::from_str(&g.generate(&tdrs).unwrap()).unwrap();
+        let rec = &json["resource"]["google_dns_record_set"]["cname-bar-example-com"];
+        assert_eq!(rec["name"], "bar.example.com.");
+        assert_eq!(rec["rrdatas"][0], "foo.example.com.");
+    }
+
+    // Each record type gets its own azurerm resource type, with names
+    // relative to the zone apex and values in that type's own shape
+    #[test]
+    fn test_azure_resource_types() {
+        let tdrs = bind::from_file("testdata.zone", "").unwrap();
+        let az = azure::Azure::new("example.com", "10.in-addr.arpa", "dns-rg");
+        let json: serde_json::Value = serde_json::from_str(&az.generate(&tdrs).unwrap()).unwrap();
+        let res = &json["resource"];
+        let foo = &res["azurerm_dns_a_record"]["a-foo-example-com"];
+        assert_eq!(foo["name"], "foo");
+        assert_eq!(foo["zone_name"], "example.com");
+        assert_eq!(foo["resource_group_name"], "dns-rg");
+        assert_eq!(foo["records"][0], "10.0.0.1");
+        assert_eq!(res["azurerm_dns_a_record"]["a-www-sub-example-com"]["name"], "www.sub");
+        assert_eq!(res["azurerm_dns_cname_record"]["cname-bar-example-com"]["record"], "foo.example.com");
+        let mx = &res["azurerm_dns_mx_record"]["mx-example-com"];
+        assert_eq!(mx["name"], "@");
+        assert_eq!(mx["record"][0]["preference"], 10);
+        assert_eq!(mx["record"][0]["exchange"], "mail.example.com");
+        let srv = &res["azurerm_dns_srv_record"]["srv-_sip-_tcp-example-com"]["record"][0];
+        assert_eq!(srv["port"], 5060);
+        assert_eq!(srv["target"], "sip.example.com");
+        assert_eq!(res["azurerm_dns_txt_record"]["txt-txt-example-com"]["record"][0]["value"],
+                   "v=spf1 a;mx \"quoted\" -all");
+        assert!(res.get("azurerm_dns_soa_record").is_none());
+    }
+
+    // Records that don't fall inside the zone they're sent to can't be given
+    // a relative name, so generation should fail
+    #[test]
+    fn test_azure_outside_zone() {
+        let tdrs = tinydns::parse_line("=foo.example.org:10.0.0.1:300");
+        let az = azure::Azure::new("example.com", "10.in-addr.arpa", "dns-rg");
+        assert!(az.generate(&tdrs).is_none());
+        let az = azure::Azure::new("example.org", "10.in-addr.arpa.", "dns-rg");
+        let json: serde_json::Value = serde_json::from_str(&az.generate(&tdrs).unwrap()).unwrap();
+        let ptr = &json["resource"]["azurerm_dns_ptr_record"]["ptr-1-0-0-10-in-addr-arpa"];
+        assert_eq!(ptr["name"], "1.0.0");
+        assert_eq!(ptr["zone_name"], "10.in-addr.arpa");
+        assert_eq!(ptr["records"][0], "foo.example.org");
+    }
+
+    // One RecordSetGroup per hosted zone, each referring to its zone ID
+    // parameter, with the records in presentation format
+    #[test]
+    fn test_cloudformation_template() {
+        let mut tdrs = bind::from_file("testdata.zone", "").unwrap();
+        tdrs.extend(tinydns::parse_line("^1.0.0.10.in-addr.arpa:foo.example.com:600"));
+        let cf = cloudformation::CloudFormation::new("Z1", "Z2", false);
+        let json: serde_json::Value = serde_json::from_str(&cf.generate(&tdrs).unwrap()).unwrap();
+        assert_eq!(json["Parameters"]["ForwardHostedZoneId"]["Default"], "Z1");
+        assert_eq!(json["Parameters"]["ReverseHostedZoneId"]["Default"], "Z2");
+        let fwd = &json["Resources"]["ForwardRecordSetGroup"];
+        assert_eq!(fwd["Type"], "AWS::Route53::RecordSetGroup");
+        assert_eq!(fwd["Properties"]["HostedZoneId"]["Ref"], "ForwardHostedZoneId");
+        let sets = fwd["Properties"]["RecordSets"].as_array().unwrap();
+        assert!(sets.iter().all(|s| s["Type"] != "SOA"));
+        let foo = sets.iter().find(|s| s["Name"] == "foo.example.com.").unwrap();
+        assert_eq!(foo["TTL"], "600");
+        assert_eq!(foo["ResourceRecords"][0], "10.0.0.1");
+        let txt = sets.iter().find(|s| s["Type"] == "TXT").unwrap();
+        assert_eq!(txt["ResourceRecords"][0], "\"v=spf1 a;mx \\\"quoted\\\" -all\"");
+        let rev = &json["Resources"]["ReverseRecordSetGroup"]["Properties"];
+        assert_eq!(rev["RecordSets"][0]["ResourceRecords"][0], "foo.example.com.");
+    }
+
+    // The YAML form should hold the same template as the JSON one
+    #[test]
+    fn test_cloudformation_yaml() {
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let json_cf = cloudformation::CloudFormation::new("Z1", "Z2", false);
+        let yaml_cf = cloudformation::CloudFormation::new("Z1", "Z2", true);
+        assert_eq!(yaml_cf.output_name("data"), "data.yaml");
+        assert_eq!(json_cf.output_dir(), "cloudformation");
+        let from_json: serde_json::Value =
+            serde_json::from_str(&json_cf.generate(&tdrs).unwrap()).unwrap();
+        let from_yaml: serde_json::Value =
+            serde_yaml::from_str(&yaml_cf.generate(&tdrs).unwrap()).unwrap();
+        assert_eq!(from_json["Resources"], from_yaml["Resources"]);
+        assert_eq!(from_json["Parameters"], from_yaml["Parameters"]);
+    }
+
+    // Record sets become UPSERTs grouped by zone, with TXT values quoted
+    // the way the Route53 API wants them
+    #[test]
+    fn test_change_batch_upserts() {
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let r53rs = route53::generate("Z1", "Z2", &tdrs).unwrap();
+        let zones = route53api::upserts(&r53rs);
+        assert_eq!(zones.keys().collect::<Vec<_>>(), vec!["Z1", "Z2"]);
+        let fwd = &zones["Z1"];
+        assert!(fwd.iter().all(|c| c.action == "UPSERT"));
+        let txt = fwd.iter().find(|c| c.resource_record_set.rtype == "TXT").unwrap();
+        assert_eq!(txt.resource_record_set.name, "txt.example.com.");
+        assert_eq!(txt.resource_record_set.resource_records[0].value, "\"Some text string\"");
+        assert_eq!(route53api::unquote_txt(&route53api::quote_txt("a \"b\"")), "a \"b\"");
+        // Non-ASCII and control bytes come back from their \NNN escapes, and
+        // \009, which isn't octal, is just an escaped 0
+        assert_eq!(route53api::quote_txt("é"), "\"\\303\\251\"");
+        assert_eq!(route53api::unquote_txt(&route53api::quote_txt("café")), "café");
+        assert_eq!(route53api::unquote_txt(&route53api::quote_txt("a\tb")), "a\tb");
+        assert_eq!(route53api::unquote_txt("\"tab\\011\" \"\\009\""), "tab\t009");
+        assert_eq!(route53api::quote_txt(&"x".repeat(300)).matches('"').count(), 4);
+    }
+
+    // Batches are split before either limit is reached, counting UPSERTs
+    // twice, and a change that can never fit is an error
+    #[test]
+    fn test_change_batch_limits() {
+        let mut r53rs = HashMap::new();
+        for i in 0..600 {
+            let rec = Route53Record::new("Z1", &format!("h{}.example.com", i), "A", "10.0.0.1", 300);
+            r53rs.insert(format!("a-h{}", i), rec);
+        }
+        let changes = route53api::upserts(&r53rs).remove("Z1").unwrap();
+        let batches = route53api::to_change_batches(changes).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].changes.len(), 500);
+        assert_eq!(batches[1].changes.len(), 100);
+        assert_eq!(batches[1].comment, Some("tiny2terra change batch 2 of 2".to_string()));
+
+        let mut big = Route53Record::new("Z1", "big.example.com", "TXT", &"x".repeat(200), 300);
+        for i in 0..100 {
+            big.add_record(&format!("{}{}", i, "y".repeat(200)));
+        }
+        let mut sets = HashMap::new();
+        sets.insert("txt-big".to_string(), big);
+        let changes = route53api::upserts(&sets).remove("Z1").unwrap();
+        assert!(route53api::to_change_batches(changes).is_err());
+    }
+
+    // Removed sets are deleted exactly as they were, ahead of the CREATEs
+    // and UPSERTs, and unchanged sets aren't touched at all
+    #[test]
+    fn test_change_batch_from_old() {
+        let mut old_tdrs = tinydns::from_file("testdata").unwrap();
+        old_tdrs.extend(tinydns::parse_line("+gone.example.com:10.0.0.9:120"));
+        let mut new_tdrs = tinydns::from_file("testdata").unwrap();
+        new_tdrs.retain(|r| r.rtype != "TXT");
+        new_tdrs.extend(tinydns::parse_line("'txt.example.com:Other text:600"));
+        new_tdrs.extend(tinydns::parse_line("Cgone.example.com:foo.example.com:300"));
+        let old = route53::generate("Z1", "Z2", &old_tdrs).unwrap();
+        let new = route53::generate("Z1", "Z2", &new_tdrs).unwrap();
+
+        let zones = route53api::changes(&old, &new);
+        assert_eq!(zones.len(), 1);
+        let summary: Vec<(&str, &str, &str)> = zones["Z1"].iter().map(|c| {
+            (c.action.as_str(), c.resource_record_set.rtype.as_str(),
+             c.resource_record_set.name.as_str())
+        }).collect();
+        assert_eq!(summary, vec![("DELETE", "A", "gone.example.com."),
+                                 ("CREATE", "CNAME", "gone.example.com."),
+                                 ("UPSERT", "TXT", "txt.example.com.")]);
+        let deleted = &zones["Z1"][0].resource_record_set;
+        assert_eq!(deleted.ttl, Some(120));
+        assert_eq!(deleted.resource_records[0].value, "10.0.0.9");
+        assert!(route53api::changes(&new, &new).is_empty());
+    }
+
+    // Route53 owns each zone's SOA and apex NS, so neither a full set of
+    // UPSERTs nor the changes between two inputs should touch them, even
+    // when the two inputs' SOA serials differ.  Delegations stay in.
+    #[test]
+    fn test_change_batch_skips_managed() {
+        let generate = |serial| {
+            let opts = tinydns::ParseOptions { serial: tinydns::SerialSource::Fixed(serial),
+                                               ..Default::default() };
+            let mut tdrs = tinydns::parse_line_with(".example.com::ns1.example.com", &opts);
+            tdrs.extend(tinydns::parse_line("&sub.example.com::ns2.example.com"));
+            tdrs.extend(tinydns::parse_line("+www.example.com:10.0.0.1"));
+            route53::generate("Z1", "Z2", &tdrs).unwrap()
+        };
+        let (old, new) = (generate(1), generate(2));
+
+        let zones = route53api::upserts(&new);
+        let summary: Vec<(&str, &str)> = zones["Z1"].iter().map(|c| {
+            (c.resource_record_set.rtype.as_str(), c.resource_record_set.name.as_str())
+        }).collect();
+        assert_eq!(summary, vec![("NS", "sub.example.com."), ("A", "www.example.com.")]);
+        assert!(route53api::changes(&old, &new).is_empty());
+    }
+
+    // The get-vanilla case from AWS's SigV4 test suite
+    #[test]
+    fn test_sigv4_signature() {
+        let req = route53client::Request {
+            method: "GET".to_string(),
+            path:   "/".to_string(),
+            query:  Vec::new(),
+            body:   String::new()
+        };
+        let creds = route53client::Credentials {
+            access_key:    "AKIDEXAMPLE".to_string(),
+            secret_key:    "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None
+        };
+        assert_eq!(route53client::amz_date(1_440_938_160), "20150830T123600Z");
+        let headers = route53client::sign(&req, "example.amazonaws.com", &creds,
+                                          "20150830T123600Z", "us-east-1", "service");
+        assert_eq!(headers[2].1, "AWS4-HMAC-SHA256 \
+                                  Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                                  SignedHeaders=host;x-amz-date, \
+                                  Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31");
+        assert_eq!(route53client::uri_encode("*.example.com"), "%2A.example.com");
+    }
+
+    // Submit a batch to a mock Route53 and poll it until it's INSYNC
+    #[test]
+    fn test_apply_against_mock() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", server.server_addr());
+        let handle = ::std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            for status in &["PENDING", "PENDING", "INSYNC"] {
+                let mut req = server.recv().unwrap();
+                let mut body = String::new();
+                req.as_reader().read_to_string(&mut body).unwrap();
+                let auth = req.headers().iter()
+                    .find(|h| h.field.equiv("Authorization"))
+                    .map(|h| h.value.to_string()).unwrap_or_default();
+                seen.push((req.method().to_string(), req.url().to_string(), auth, body));
+                let xml = format!("<GetChangeResponse xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+                                   <ChangeInfo><Id>/change/C123</Id><Status>{}</Status>\
+                                   <SubmittedAt>2015-08-30T12:36:00Z</SubmittedAt></ChangeInfo>\
+                                   </GetChangeResponse>", status);
+                req.respond(tiny_http::Response::from_string(xml)).unwrap();
+            }
+            seen
+        });
+
+        let tdrs = tinydns::from_file("testdata").unwrap();
+        let r53rs = route53::generate("Z1", "Z2", &tdrs).unwrap();
+        let changes = route53api::upserts(&r53rs).remove("Z1").unwrap();
+        let batch = &route53api::to_change_batches(changes).unwrap()[0];
+        let creds = route53client::Credentials {
+            access_key:    "AKID".to_string(),
+            secret_key:    "secret".to_string(),
+            session_token: None
+        };
+        let client = route53client::Client::new(&endpoint, creds);
+        let info = client.change_resource_record_sets("/hostedzone/Z1", batch).unwrap();
+        assert_eq!(info.id, "C123");
+        assert_eq!(info.status, "PENDING");
+        let info = client.wait_for_change(&info.id, ::std::time::Duration::from_millis(10),
+                                          ::std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(info.status, "INSYNC");
+
+        let seen = handle.join().unwrap();
+        assert_eq!(seen[0].0, "POST");
+        assert_eq!(seen[0].1, "/2013-04-01/hostedzone/Z1/rrset");
+        assert!(seen[0].2.starts_with("AWS4-HMAC-SHA256 Credential=AKID/"));
+        assert!(seen[0].3.contains("<Action>UPSERT</Action>"));
+        assert!(seen[0].3.contains("<Value>&quot;Some text string&quot;</Value>"));
+        assert_eq!(seen[1].1, "/2013-04-01/change/C123");
+        assert_eq!(seen[2].0, "GET");
+    }
+
+    // List a zone from a mock Route53 that splits it over two pages, then
+    // turn it into tinydns data with the alias left as a comment
+    #[test]
+    fn test_fetch_against_mock() {
+        let pages = vec![
+            "<ListResourceRecordSetsResponse xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+             <ResourceRecordSets>\
+             <ResourceRecordSet><Name>\\052.example.com.</Name><Type>A</Type><TTL>300</TTL>\
+             <ResourceRecords><ResourceRecord><Value>10.0.0.1</Value></ResourceRecord>\
+             </ResourceRecords></ResourceRecordSet>\
+             <ResourceRecordSet><Name>example.com.</Name><Type>CAA</Type><TTL>300</TTL>\
+             <ResourceRecords><ResourceRecord><Value>0 issue \"ca.example.net\"</Value></ResourceRecord>\
+             </ResourceRecords></ResourceRecordSet>\
+             </ResourceRecordSets><IsTruncated>true</IsTruncated>\
+             <NextRecordName>txt.example.com.</NextRecordName><NextRecordType>TXT</NextRecordType>\
+             <MaxItems>2</MaxItems></ListResourceRecordSetsResponse>",
+            "<ListResourceRecordSetsResponse xmlns=\"https://route53.amazonaws.com/doc/2013-04-01/\">\
+             <ResourceRecordSets>\
+             <ResourceRecordSet><Name>txt.example.com.</Name><Type>TXT</Type><TTL>600</TTL>\
+             <ResourceRecords><ResourceRecord><Value>\"a:b\"</Value></ResourceRecord>\
+             </ResourceRecords></ResourceRecordSet>\
+             <ResourceRecordSet><Name>www.example.com.</Name><Type>A</Type>\
+             <AliasTarget><HostedZoneId>Z2FDTNDATAQYW2</HostedZoneId>\
+             <DNSName>d111.cloudfront.net.</DNSName>\
+             <EvaluateTargetHealth>false</EvaluateTargetHealth></AliasTarget></ResourceRecordSet>\
+             </ResourceRecordSets><IsTruncated>false</IsTruncated><MaxItems>2</MaxItems>\
+             </ListResourceRecordSetsResponse>"
+        ];
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", server.server_addr());
+        let handle = ::std::thread::spawn(move || {
+            let mut urls = Vec::new();
+            for page in pages {
+                let req = server.recv().unwrap();
+                urls.push(req.url().to_string());
+                req.respond(tiny_http::Response::from_string(page)).unwrap();
+            }
+            urls
+        });
+
+        let creds = route53client::Credentials {
+            access_key:    "AKID".to_string(),
+            secret_key:    "secret".to_string(),
+            session_token: None
+        };
+        let client = route53client::Client::new(&endpoint, creds);
+        let sets = client.list_resource_record_sets("Z1").unwrap();
+        let urls = handle.join().unwrap();
+        assert_eq!(urls, vec!["/2013-04-01/hostedzone/Z1/rrset",
+                              "/2013-04-01/hostedzone/Z1/rrset?name=txt.example.com.&type=TXT"]);
+        assert_eq!(sets.len(), 4);
+        assert!(sets[3].alias_target.is_some());
+
+        let (tdrs, comments) = route53api::to_tinydns(&sets);
+        assert_eq!(comments, vec!["# Skipped A www.example.com: alias to d111.cloudfront.net."]);
+        let lines = tinydns::to_data(&tdrs);
+        assert!(lines.contains(&"+*.example.com:10.0.0.1:300".to_string()));
+        assert!(lines.contains(&"'txt.example.com:a\\072b:600".to_string()));
+        assert_eq!(tdrs.iter().find(|r| r.rtype == "CAA").unwrap().target, "0 issue ca.example.net");
+    }
+
+    // Names relative to the zone, sorted the way octoDNS insists on, with
+    // per-type value structures and names sharing several types as lists
+    #[test]
+    fn test_octodns_zone_files() {
+        let tdrs = bind::from_file("testdata.zone", "").unwrap();
+        let files = octodns::to_zone_files(&tdrs).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "example.com");
+        let doc: serde_yaml::Value = serde_yaml::from_str(&files[0].1).unwrap();
+        let apex = doc[""].as_sequence().unwrap();
+        assert_eq!(apex[0]["type"], serde_yaml::Value::from("MX"));
+        assert_eq!(apex[0]["value"]["exchange"], serde_yaml::Value::from("mail.example.com."));
+        assert_eq!(apex[0]["value"]["preference"], serde_yaml::Value::from(10));
+        assert_eq!(apex[1]["value"], serde_yaml::Value::from("ns1.example.com."));
+        assert_eq!(doc["foo"]["ttl"], serde_yaml::Value::from(600));
+        assert_eq!(doc["www.sub"]["value"], serde_yaml::Value::from("10.0.1.1"));
+        assert_eq!(doc["_sip._tcp"]["value"]["port"], serde_yaml::Value::from(5060));
+        assert_eq!(doc["sub"]["value"]["tag"], serde_yaml::Value::from("issue"));
+        assert_eq!(doc["txt"]["value"], serde_yaml::Value::from("v=spf1 a\\;mx \"quoted\" -all"));
+        let keys: Vec<&str> = doc.as_mapping().unwrap().iter()
+            .map(|(k, _)| k.as_str().unwrap()).collect();
+        assert_eq!(keys, vec!["", "_sip._tcp", "after", "bar", "foo", "ns1", "sub", "txt", "www.sub"]);
+    }
+
+    #[test]
+    fn test_octodns_natural_order() {
+        let mut names = vec!["host10", "host2", "", "a", "host1b", "host1"];
+        names.sort_by(|a, b| octodns::natural_cmp(a, b));
+        assert_eq!(names, vec!["", "a", "host1", "host1b", "host2", "host10"]);
+    }
+
+    // One D() per zone with relative labels, absolute targets, escaped TXT
+    // and TTL() only where it differs from the zone's default
+    #[test]
+    fn test_dnscontrol_config() {
+        let mut tdrs = bind::from_file("testdata.zone", "").unwrap();
+        tdrs.extend(tinydns::parse_line("Z0.0.10.in-addr.arpa:ns1.example.com:hostmaster.example.com:1:2:3:4:5:300"));
+        tdrs.extend(tinydns::parse_line("^1.0.0.10.in-addr.arpa:foo.example.com:300"));
+        tdrs.extend(tinydns::parse_line("+elsewhere.example.org:10.0.9.9:300"));
+        let js = dnscontrol::to_dnsconfig(&tdrs, "r53").unwrap();
+        assert!(js.starts_with("var REG_NONE = NewRegistrar(\"none\");\nvar DSP = NewDnsProvider(\"r53\");\n"));
+        assert!(js.contains("D(\"example.com\", REG_NONE, DnsProvider(DSP),\n    DefaultTTL(3600),\n"));
+        assert!(js.contains("    MX(\"@\", 10, \"mail.example.com.\"),\n"));
+        assert!(js.contains("    A(\"foo\", \"10.0.0.1\", TTL(600)),\n"));
+        assert!(js.contains("    TXT(\"txt\", \"v=spf1 a;mx \\\"quoted\\\" -all\"),\n"));
+        assert!(js.contains("    CAA(\"sub\", \"issue\", \"letsencrypt.org\"),\n"));
+        assert!(js.contains("    A(\"after\", \"10.0.0.2\")\n);\n"));
+        assert!(js.contains("D(\"0.0.10.in-addr.arpa\", REG_NONE, DnsProvider(DSP),\n    \
+                             DefaultTTL(300),\n    PTR(\"1\", \"foo.example.com.\")\n);\n"));
+        assert!(!js.contains("SOA") && !js.contains("elsewhere"));
+        assert_eq!(dnscontrol::js_string("a\\b\u{1}"), "\"a\\\\b\\u0001\"");
+    }
+
+    // Apex CNAMEs, CNAMEs sharing a name and duplicate CNAMEs should all be
+    // reported against the lines they came from
+    #[test]
+    fn test_validate_cname_conflicts() {
+        let tdrs = tinydns::from_file("testdata-cname").unwrap();
+        let strict = validate::Options { strict: true, ..Default::default() };
+        let problems: Vec<String> = validate::check(&tdrs, &strict).iter()
+            .filter(|p| p.severity == validate::Severity::Error)
+            .map(|p| p.to_text()).collect();
+        assert_eq!(problems, vec![
+            "lines 2, 3: example.com: CNAME at a zone apex",
+            "lines 4, 5, 6: bar.example.com: CNAME alongside other records (A, TXT)",
+            "lines 7, 8: baz.example.com: more than one CNAME"]);
+        let options = validate::Options::default();
+        // Without --strict they're only warnings
+        assert!(validate::check(&tdrs, &options).iter()
+                .all(|p| p.severity == validate::Severity::Warning));
+        assert!(validate::check(&tinydns::from_file("testdata").unwrap(), &options).is_empty());
+        // Its MX points at a mail host it has no address for, which is only a warning
+        assert!(validate::check(&bind::from_file("testdata.zone", "").unwrap(), &options).iter()
+                .all(|p| p.severity == validate::Severity::Warning));
+    }
+
+    // Loops are errors; long chains and CNAMEs to names in our zones with
+    // no records are warnings.  Targets outside our zones are left alone.
+    #[test]
+    fn test_validate_cname_chains() {
+        let tdrs = tinydns::from_file("testdata-cnamechain").unwrap();
+        let problems = validate::cname_chains(&tdrs, 3);
+        let summary: Vec<(validate::Severity, String)> = problems.iter()
+            .map(|p| (p.severity, p.to_text())).collect();
+        assert_eq!(summary, vec![
+            (validate::Severity::Warning,
+             "line 10: dangling.example.com: CNAME to nothing.example.com, which has no records".to_string()),
+            (validate::Severity::Error,
+             "lines 4, 5: a.example.com: CNAME loop: a.example.com -> b.example.com -> a.example.com".to_string()),
+            (validate::Severity::Warning,
+             "lines 6, 7, 8, 9: head.example.com: CNAME chain of 4 hops, more than 3: \
+              head.example.com -> c1.example.com -> c2.example.com -> c3.example.com -> ns1.example.com".to_string())]);
+        assert_eq!(validate::cname_chains(&tdrs, 4).len(), 2);
+        // The loop only stops output when checking strictly
+        let errors = |strict| validate::check(&tdrs, &validate::Options { strict, ..Default::default() })
+            .iter().filter(|p| p.severity == validate::Severity::Error).count();
+        assert_eq!((errors(false), errors(true)), (0, 1));
+    }
+
+    // CNAME and IP address targets are errors; in-zone targets without
+    // addresses are warnings, and targets outside our zones are left alone
+    #[test]
+    fn test_validate_host_targets() {
+        let tdrs = tinydns::from_file("testdata-targets").unwrap();
+        let problems = validate::check(&tdrs, &Default::default());
+        let summary: Vec<(validate::Severity, String)> = problems.iter()
+            .map(|p| (p.severity, p.to_text())).collect();
+        assert_eq!(summary, vec![
+            (validate::Severity::Error,
+             "line 5: sub.example.com: NS target 10.0.0.54 is an IP address, not a hostname".to_string()),
+            (validate::Severity::Error,
+             "lines 6, 7: example.com: MX target alias.example.com is a CNAME".to_string()),
+            (validate::Severity::Warning,
+             "line 8: example.com: MX target nowhere.example.com has no A or AAAA records".to_string())]);
+    }
+
+    // Reverse names and CIDR ranges, IPv4 and IPv6
+    #[test]
+    fn test_reverse_ranges() {
+        assert_eq!(reverse::ptr_address("4.3.2.1.in-addr.arpa."), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(reverse::ptr_address("3.2.1.in-addr.arpa"), None);
+        assert_eq!(reverse::ptr_address(&format!("1.{}8.b.d.0.1.0.0.2.ip6.arpa", "0.".repeat(23))),
+                   Some("2001:db8::1".parse().unwrap()));
+        let zone = reverse::Range::from_zone("8.b.d.0.1.0.0.2.ip6.arpa").unwrap();
+        assert_eq!(zone, reverse::Range::parse("2001:db8::/32").unwrap());
+        assert!(zone.contains(&"2001:db8::1".parse().unwrap()));
+        assert!(!zone.contains(&"10.0.0.1".parse().unwrap()));
+        let range = reverse::Range::parse("10.0.0.0/23").unwrap();
+        assert!(range.contains(&"10.0.1.255".parse().unwrap()));
+        assert!(!range.contains(&"10.0.2.0".parse().unwrap()));
+        assert!(reverse::Range::parse("0.0.0.0/0").unwrap().contains(&"192.0.2.1".parse().unwrap()));
+        assert!(reverse::Range::parse("10.0.0.0/33").is_err());
+        assert!(reverse::Range::parse("example.com").is_err());
+    }
+
+    // PTRs without a matching A, As in the reverse zone without a PTR, and
+    // addresses with PTRs to different names
+    #[test]
+    fn test_reverse_report() {
+        let tdrs = tinydns::from_file("testdata-reverse").unwrap();
+        let report = reverse::report(&tdrs, &[]);
+        assert_eq!(report.to_text(), "\
+PTR without matching A: 10.0.0.1 -> alias.example.com (line 7)
+PTR without matching A: 10.0.0.3 -> gone.example.com (line 6)
+A without PTR: host2.example.com -> 10.0.0.2 (line 4)
+Conflicting PTRs: 10.0.0.1 -> alias.example.com, host1.example.com (lines 3, 7)
+Skipped: PTR for example.com, which isn't a reverse address
+2 PTR without A, 1 A without PTR, 1 conflicting, 1 skipped");
+
+        // Given ranges replace the zones in the data
+        let ranges = vec![reverse::Range::parse("192.168.0.0/16").unwrap()];
+        let report = reverse::report(&tdrs, &ranges);
+        assert_eq!(report.a_without_ptr, vec![reverse::Mismatch {
+            address: "192.168.1.1".to_string(),
+            name:    "outside.example.com".to_string(),
+            lines:   vec![5]
+        }]);
+        assert!(reverse::report(&tinydns::from_file("testdata").unwrap(), &[]).is_empty());
+    }
+
+    // Names and hostname targets must follow RFC 1035/1123, with service
+    // labels and leftmost wildcards allowed, and problems name the field
+    #[test]
+    fn test_validate_hostnames() {
+        let tdrs = tinydns::from_file("testdata-hostnames").unwrap();
+        let problems: Vec<String> = validate::check(&tdrs, &Default::default()).iter()
+            .map(|p| p.to_text()).collect();
+        assert_eq!(problems, vec![
+            "line 6: bad_host.example.com: A name has a label with characters other than \
+             letters, digits and hyphens (or a leading underscore): bad_host",
+            "line 7: www..example.com: A name has an empty label",
+            "line 8: foo.*.example.com: A name has a wildcard that isn't the leftmost label",
+            "line 9: -dash.example.com: A name has a label starting or ending with a hyphen: -dash",
+            &format!("line 10: {0}.example.com: A name has a label of 64 octets, more than 63: {0}",
+                     "a".repeat(64)),
+            "line 11: www.example.com: CNAME target *.example.org has a wildcard label"]);
+
+        // They only stop output when checking strictly
+        let severities = |strict| -> Vec<validate::Severity> {
+            let options = validate::Options { strict, ..Default::default() };
+            validate::check(&tdrs, &options).iter().map(|p| p.severity).collect()
+        };
+        assert!(severities(false).iter().all(|s| *s == validate::Severity::Warning));
+        assert!(severities(true).iter().all(|s| *s == validate::Severity::Error));
+
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(validate::hostname_problem(&long, false),
+                   Some("is 255 octets long, more than 253".to_string()));
+        assert_eq!(validate::hostname_problem("_sip._tcp.example.com.", false), None);
+        assert!(validate::hostname_problem("_.example.com", false).is_some());
+        // RFC 2317 classless reverse names aren't held to hostname rules
+        assert_eq!(validate::hostname_problem("0/25.0.0.10.IN-ADDR.ARPA.", false), None);
+        assert!(validate::hostname_problem("0/25.0.0.10.in-addr.arpa.example.com", false).is_some());
+    }
+
+    // Unicode names and targets become punycode, ASCII ones are untouched,
+    // and the map gives the Unicode form of each converted name
+    #[test]
+    fn test_idn_to_ascii() {
+        assert_eq!(idn::to_ascii("bücher.example.").unwrap(), "xn--bcher-kva.example.");
+        assert_eq!(idn::to_ascii("Plain.Example.com").unwrap(), "Plain.Example.com");
+        assert_eq!(idn::to_unicode("WWW.xn--bcher-kva.example."), Some("www.bücher.example".to_string()));
+        assert_eq!(idn::to_unicode("www.example.com"), None);
+
+        let mut tdrs = tinydns::from_file("testdata-idn").unwrap();
+        let names = idn::to_ascii_records(&mut tdrs);
+        assert_eq!(tdrs[0].fqdn, "xn--bcher-kva.example");
+        assert_eq!(tdrs[2].fqdn, "www.xn--bcher-kva.example");
+        assert_eq!(tdrs[3].target, "www.xn--bcher-kva.example.");
+        assert_eq!(tdrs[4].target, "10 mx.xn--bcher-kva.example");
+        assert_eq!(tdrs[6].fqdn, "plain.example.com");
+        assert_eq!(names.len(), 6);
+        assert_eq!(names["www.xn--bcher-kva.example"], "www.bücher.example");
+        assert!(validate::check(&tdrs, &Default::default()).is_empty());
+    }
+
+    // Unicode names that won't convert, and punycode that won't decode, are
+    // reported by validation
+    #[test]
+    fn test_validate_idn() {
+        assert_eq!(validate::hostname_problem("bücher.example", false), None);
+        assert_eq!(validate::hostname_problem("\u{301}bc.example", false),
+                   Some("isn't a valid internationalised name (IDNA 2008/UTS 46)".to_string()));
+        assert_eq!(validate::hostname_problem("xn--zz.example", false),
+                   Some("has a punycode label that doesn't decode: xn--zz".to_string()));
+        assert!(idn::to_ascii("\u{301}bc.example").is_err());
+    }
+
+    // Records without a TTL get the configured default, from either parser
+    #[test]
+    fn test_default_ttl() {
+        let opts = tinydns::ParseOptions { default_ttl: 900, ..Default::default() };
+        assert_eq!(tinydns::parse_line_with("+www.foo.com:1.2.3.4", &opts)[0].ttl, 900);
+        assert_eq!(tinydns::parse_line_with("@foo.com::mx.foo.com:10", &opts)[0].ttl, 900);
+        assert_eq!(tinydns::parse_line_with("Zfoo.com:ns.foo.com:hm.foo.com", &opts)[0].ttl, 900);
+        assert_eq!(tinydns::parse_line_with("+www.foo.com:1.2.3.4:60", &opts)[0].ttl, 60);
+        assert_eq!(tinydns::parse_line("+www.foo.com:1.2.3.4")[0].ttl, tinydns::DEFAULT_TTL);
+
+        // The included fixture has no $TTL of its own
+        let tdrs = bind::from_file_with("testdata-include.zone", "sub.example.com", 900).unwrap();
+        assert!(tdrs.iter().all(|r| r.ttl == 900));
+    }
+
+    // Each bound comes from the most specific part of the policy that sets
+    // it, and TTLs outside are rejected or clamped as the policy says
+    #[test]
+    fn test_ttl_policy() {
+        let mut policy = ttl::Policy::from_file("testdata-ttlpolicy.yaml").unwrap();
+        assert_eq!(policy.limits("A", None), ttl::Limits { min: Some(60), max: Some(86400) });
+        assert_eq!(policy.limits("NS", Some("example.org")).min, Some(3600));
+        assert_eq!(policy.limits("TXT", Some("example.org")).min, Some(1200));
+        assert_eq!(policy.limits("NS", Some("example.com")).min, Some(3600));
+        assert_eq!(policy.limits("MX", Some("EXAMPLE.org.")).min, Some(3600));
+
+        let mut tdrs = tinydns::from_file("testdata-ttl").unwrap();
+        let problems: Vec<(validate::Severity, String)> = validate::ttls(&tdrs, &policy).iter()
+            .map(|p| (p.severity, p.to_text())).collect();
+        assert_eq!(problems, vec![
+            (validate::Severity::Error, "line 3: example.com: TTL 600 is below the NS minimum of 3600".to_string()),
+            (validate::Severity::Error, "line 4: ns1.example.com: TTL 30 is below the A minimum of 60".to_string()),
+            (validate::Severity::Error,
+             "line 7: mail.example.com: TTL 100000 is above the A maximum of 86400".to_string()),
+            (validate::Severity::Error, "line 9: example.org: TTL 600 is below the TXT minimum of 1200".to_string()),
+            (validate::Severity::Error, "line 10: example.org: TTL 2400 is below the MX minimum of 3600".to_string()),
+            (validate::Severity::Error, "line 11: example.org: TTL 1800 is below the NS minimum of 3600".to_string())]);
+
+        policy.action = ttl::Action::Clamp;
+        let options = validate::Options { ttl_policy: Some(&policy), ..Default::default() };
+        assert!(validate::check(&tdrs, &options).iter().all(|p| p.severity == validate::Severity::Warning));
+        policy.clamp(&mut tdrs);
+        let ttls: Vec<u32> = tdrs.iter().map(|r| r.ttl).collect();
+        assert_eq!(ttls, vec![3600, 3600, 60, 300, 600, 86400, 3600, 1200, 3600, 3600]);
+        assert!(validate::ttls(&tdrs, &policy).is_empty());
+
+        assert_eq!(ttl::Policy::from_file("testdata-badttlpolicy.yaml").unwrap_err(),
+                   "Error in TTL policy testdata-badttlpolicy.yaml: \
+                    min 1200 is above max 600 for NS records in example.org");
+    }
+
+    // Serials for SOAs that don't give one come from the chosen source, on
+    // a clock the test controls
+    #[test]
+    fn test_soa_serial_sources() {
+        fn clock() -> u64 { 1_700_000_000 } // 2023-11-14T22:13:20Z
+        let opts = |serial| tinydns::ParseOptions { serial, clock, ..Default::default() };
+        let serial = |o: &tinydns::ParseOptions, line: &str| {
+            tinydns::parse_line_with(line, o).last().unwrap().target.split(' ').nth(2).unwrap().to_string()
+        };
+
+        let now = opts(tinydns::SerialSource::Now);
+        assert_eq!(serial(&now, "Zfoo.com:ns.foo.com:hm.foo.com"), "1700000000");
+        assert_eq!(serial(&now, ".foo.com::ns.foo.com"), "1700000000");
+        assert_eq!(serial(&now, "Zfoo.com:ns.foo.com:hm.foo.com:7"), "7");
+        let date = opts(tinydns::SerialSource::Date(0));
+        assert_eq!(serial(&date, "Zfoo.com:ns.foo.com:hm.foo.com"), "2023111400");
+        assert_eq!(serial(&opts(tinydns::SerialSource::Date(3)), ".foo.com::ns.foo.com"), "2023111403");
+        let fixed = opts(tinydns::SerialSource::Fixed(42));
+        assert_eq!(serial(&fixed, ".foo.com::ns.foo.com"), "42");
+        assert_eq!(opts(tinydns::SerialSource::Mtime).default_serial(None), Ok(1_700_000_000));
+        assert_eq!(opts(tinydns::SerialSource::Mtime).default_serial(Some(5)), Ok(5));
+
+        // Every SOA in a file gets the same serial
+        let tdrs = tinydns::from_file_with("testdata-ttl", &date).unwrap();
+        let serials: Vec<&str> = tdrs.iter().filter(|r| r.rtype == "SOA")
+            .map(|r| r.target.split(' ').nth(2).unwrap()).collect();
+        assert_eq!(serials, vec!["2023111400", "2023111400"]);
+
+        assert_eq!(tinydns::civil_date(951_782_400), (2000, 2, 29));
+        assert_eq!(tinydns::SerialSource::parse("date"), Ok(tinydns::SerialSource::Date(0)));
+        assert_eq!(tinydns::SerialSource::parse("date:12"), Ok(tinydns::SerialSource::Date(12)));
+        assert!(tinydns::SerialSource::parse("date:100").is_err());
+        assert!(tinydns::SerialSource::parse("date:").is_err());
+        assert_eq!(tinydns::SerialSource::parse("2019041101"),
+                   Ok(tinydns::SerialSource::Fixed(2019041101)));
+        assert!(tinydns::SerialSource::parse("tomorrow").is_err());
+
+        // Times past 2106 don't fit in a serial, and aren't wrapped to fit
+        fn late() -> u64 { 1 << 32 }
+        let late = |serial| tinydns::ParseOptions { serial, clock: late, ..Default::default() };
+        assert!(late(tinydns::SerialSource::Now).default_serial(None).is_err());
+        assert!(late(tinydns::SerialSource::Mtime).default_serial(None).is_err());
+        assert_eq!(late(tinydns::SerialSource::Mtime).default_serial(Some(5)), Ok(5));
+        assert_eq!(late(tinydns::SerialSource::Date(0)).default_serial(None), Ok(2106020700));
+    }
+
+    // SOURCE_DATE_EPOCH is read when it's the source, and an error if it's
+    // missing and a serial is needed
+    #[test]
+    fn test_soa_serial_source_date_epoch() {
+        fn set(name: &str) -> Option<String> {
+            match name {
+                "SOURCE_DATE_EPOCH" => Some("1234567890".to_string()),
+                _ => None
+            }
+        }
+        fn bad(_: &str) -> Option<String> { Some("soon".to_string()) }
+        fn big(_: &str) -> Option<String> { Some("4294967296".to_string()) }
+        fn unset(_: &str) -> Option<String> { None }
+        let opts = |env| tinydns::ParseOptions { serial: tinydns::SerialSource::SourceDateEpoch,
+                                                 env, ..Default::default() };
+        assert_eq!(opts(set).default_serial(None), Ok(1234567890));
+        assert!(opts(bad).default_serial(None).is_err());
+        assert!(opts(big).default_serial(None).is_err());
+        let opts = opts(unset);
+        assert!(opts.default_serial(None).is_err());
+        assert!(tinydns::parse_line_with("Zfoo.com:ns.foo.com:hm.foo.com", &opts).is_empty());
+        assert!(!tinydns::parse_line_with("Zfoo.com:ns.foo.com:hm.foo.com:1", &opts).is_empty());
+    }
+}
@@ -4,115 +4,661 @@ extern crate serde_json;
 #[macro_use] extern crate clap;
 
 use tiny2terra::types::*;
+use tiny2terra::provider::Provider;
 use tiny2terra::route53;
+use tiny2terra::cloudflare;
+use tiny2terra::google;
+use tiny2terra::azure;
+use tiny2terra::cloudformation;
 use tiny2terra::tinydns;
+use tiny2terra::diff;
+use tiny2terra::route53api;
+use tiny2terra::route53client;
+use tiny2terra::bind;
+use tiny2terra::octodns;
+use tiny2terra::dnscontrol;
+use tiny2terra::validate;
+use tiny2terra::reverse;
+use tiny2terra::idn;
+use tiny2terra::ttl;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::collections::HashMap;
-use clap::App;
+use std::collections::{BTreeMap, HashMap};
+use std::cell::RefCell;
+use std::time::Duration;
+use clap::{App, ArgMatches};
 
 // Main method uses Clap crate to build a fancy CLI from contents of cli.yml.
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).version(clap::crate_version!()).get_matches();
 
-    // If no output dir specified, default to './terraform'
-    let outdir = matches.value_of("output").unwrap_or("terraform");
-
-    // As per Clap's documentation, it's safe to simply unwrap this since
-    // it is marked as required:true
-    let infile = matches.value_of("input").unwrap();
+    // Every subcommand reads an input file except fetch, which reads from
+    // the Route53 API and names its output after the zone instead
+    let infile = match (matches.subcommand(), matches.value_of("input")) {
+        (("fetch", Some(fetch_flags)), _) => fetch_flags.value_of("zone").unwrap(),
+        (_, Some(x)) => x,
+        (_, None) => {
+            println!("An input file (-i) is required, see --help");
+            std::process::exit(1);
+        }
+    };
 
     // We'll need to get the base filename from 'infile' since 'outdir' is
     // relative to the directory we're executing from and stupid things will
     // happen otherwise.
     let basename = std::path::Path::new(infile).file_name().unwrap().to_str().unwrap();
 
-    // Attempt to create our destination directory and file if it does not exist
-    // No sense going through all the bother of parsing if we can't even output!
-    let outfile = format!("{}/{}.tf", &outdir, &basename);
-    match std::fs::create_dir_all(&outdir) {
-        Ok(_) => {},
+    // Read the TTL policy, if there is one
+    let ttl_policy = match matches.value_of("ttl_policy") {
+        Some(fname) => match ttl::Policy::from_file(fname) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None
+    };
+
+    // Has a default, so only a bad number gets clap's usual error
+    let default_ttl = value_t!(matches, "default_ttl", u32).unwrap_or_else(|e| e.exit());
+    if default_ttl > tinydns::MAX_TTL {
+        println!("The default TTL can't be more than {}", tinydns::MAX_TTL);
+        std::process::exit(1);
+    }
+
+    // Has a default too
+    let serial = match tinydns::SerialSource::parse(matches.value_of("serial").unwrap()) {
+        Ok(x) => x,
         Err(e) => {
-            println!("Error creating directory {}: {}", &outdir, e);
-            println!("Bailing out");
+            println!("{}", e);
             std::process::exit(1);
         }
     };
 
-    let ofile = match File::create(&outfile) {
-        Ok(f) => f,
-        Err(e) => {
-            println!("Error creating output file {}: {}", &outfile, e);
-            println!("Bailing out");
+    // Linting is strict unless asked not to be, everything else only if
+    // asked
+    let strict = matches.is_present("strict") ||
+                 (matches.is_present("lint") && !matches.is_present("no_strict"));
+
+    // Work out how the input file should be read
+    let idn_names = RefCell::new(BTreeMap::new());
+    let input = Input {
+        file: infile,
+        bind_origin: match matches.is_present("bind_input") {
+            true => Some(matches.value_of("origin").unwrap_or("")),
+            false => None
+        },
+        checks: validate::Options {
+            // Has a default, so only a bad number gets clap's usual error
+            max_cname_depth: value_t!(matches, "max_cname_depth", usize).unwrap_or_else(|e| e.exit()),
+            ttl_policy: ttl_policy.as_ref(),
+            strict
+        },
+        idn_names: &idn_names,
+        parse: tinydns::ParseOptions {
+            strict,
+            default_ttl,
+            serial,
+            ..Default::default()
+        }
+    };
+
+    // Each subcommand hands back the default output directory and a list of
+    // files to write, as (name, contents) pairs.
+    // Terraform provider subcommands all share the same plumbing
+    let provider = match matches.subcommand() {
+        (name, Some(flags)) => provider_for(name, flags),
+        _ => None
+    };
+    let (default_dir, mut outputs) = match (matches.subcommand(), provider) {
+        (_, Some(provider)) => {
+            let outname = provider.output_name(basename);
+            (provider.output_dir(), vec![(outname, provider_output(&input, provider.as_ref()))])
+        },
+        (("diff", Some(diff_flags)), _) => {
+            // Diff prints its own results and exits with a status code
+            diff_output(&input, diff_flags);
+        },
+        (("compare", Some(cmp_flags)), _) => {
+            // As does compare
+            compare_output(&input, cmp_flags);
+        },
+        (("reverse", Some(rev_flags)), _) => {
+            // As does the reverse report
+            reverse_output(&input, rev_flags);
+        },
+        (("tinydns", Some(_)), _) => {
+            // Strip the Terraform suffixes back off so 'data.tf' becomes 'data'
+            let stem = basename.trim_end_matches(".json").trim_end_matches(".tf");
+            ("tinydns", vec![(stem.to_string(), tinydns_output(infile))])
+        },
+        (("bind", Some(_)), _) => {
+            ("bind", bind_output(&input))
+        },
+        (("octodns", Some(_)), _) => {
+            ("octodns", octodns_output(&input))
+        },
+        (("dnscontrol", Some(dc_flags)), _) => {
+            ("dnscontrol", vec![("dnsconfig.js".to_string(), dnscontrol_output(&input, dc_flags))])
+        },
+        (("changebatch", Some(cb_flags)), _) => {
+            ("changebatch", changebatch_output(&input, cb_flags))
+        },
+        (("fetch", Some(fetch_flags)), _) => {
+            ("tinydns", vec![(basename.to_string(), fetch_output(fetch_flags))])
+        },
+        (("apply", Some(apply_flags)), _) => {
+            // Apply reports as it goes and exits
+            apply_output(&input, apply_flags);
+        },
+        _ => {
+            println!("No subcommand given, see --help for a list");
             std::process::exit(1);
         }
     };
 
-    // Subcommand: route53
-    if let Some(r53_flags) = matches.subcommand_matches("route53") {
-        // More required args to unwrap
-        let fwdzone = r53_flags.value_of("fwdzone").unwrap();
-        let ptrzone = r53_flags.value_of("ptrzone").unwrap();
+    // Map the punycode names written back to the Unicode they came from
+    if matches.is_present("idn_map") && !idn_names.borrow().is_empty() {
+        match serde_json::to_string_pretty(&*idn_names.borrow()) {
+            Ok(x) => outputs.push(("idn-map.json".to_string(), x)),
+            Err(e) => {
+                println!("Error serializing JSON: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // If the -s flag was supplied, go ahead and print to STDOUT now
+    if matches.is_present("stdout") {
+        for (_, outstring) in &outputs {
+            println!("{}", outstring);
+        }
+    }
+
+    // If we're just linting the file, exit now.
+    if matches.is_present("lint") {
+        println!("No errors detected while processing {}", infile);
+        std::process::exit(0);
+    }
+
+    // If no output dir specified, use the subcommand's default
+    let outdir = matches.value_of("output").unwrap_or(default_dir);
+    for (outname, outstring) in &outputs {
+        let outfile = format!("{}/{}", &outdir, &outname);
+        write_output(outdir, &outfile, outstring);
+
+        // Complete
+        println!("Successfully processed {} and wrote {}", infile, outfile);
+    }
+}
+
+// Where the DNS records come from and how to read them
+struct Input<'a> {
+    file: &'a str,
+    // Some(starting $ORIGIN) if the input is a BIND zone file rather than
+    // TinyDNS data
+    bind_origin: Option<&'a str>,
+    // Settings for validating the records once they're read
+    checks: validate::Options<'a>,
+    // ASCII forms of any Unicode names read, mapped to the Unicode
+    idn_names: &'a RefCell<BTreeMap<String, String>>,
+    // How TinyDNS data's numeric fields are parsed
+    parse: tinydns::ParseOptions
+}
 
-        // Process the input file into a Vec of TinyDNSRecords
-        let tdns_records = match tinydns::from_file(&infile) {
+impl<'a> Input<'a> {
+    // Parse the input file into a Vec of TinyDNSRecords and validate them,
+    // bailing out on errors
+    fn records(&self) -> Vec<TinyDNSRecord> {
+        let parsed = match self.bind_origin {
+            Some(origin) => bind::from_file_with(self.file, origin, self.parse.default_ttl),
+            None => tinydns::from_file_with(self.file, &self.parse)
+        };
+        let mut tdns_records = match parsed {
             Some(x) => x,
             None => {
-                println!("Errors while parsing file: {}", infile);
+                println!("Errors while parsing file: {}", self.file);
                 println!("Bailing out");
                 std::process::exit(1);
             }
         };
 
-        // Process the TinyDNSRecords into Route53Records
-        let r53_records = match route53::generate(&fwdzone, &ptrzone, &tdns_records) {
-            Some(x) => x,
-            None => {
-                println!("Errors while generating Route53 Records");
-                println!("Bailing out");
+        // Unicode names are written out as punycode
+        self.idn_names.borrow_mut().append(&mut idn::to_ascii_records(&mut tdns_records));
+
+        // Report everything, but only errors stop us
+        let problems = validate::check(&tdns_records, &self.checks);
+        for p in &problems {
+            match p.severity {
+                validate::Severity::Error => println!("Error in {}: {}", self.file, p.to_text()),
+                validate::Severity::Warning => println!("Warning in {}: {}", self.file, p.to_text())
+            }
+        }
+        if problems.iter().any(|p| p.severity == validate::Severity::Error) {
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+
+        // Anything left outside the TTL policy is to be clamped
+        if let Some(policy) = self.checks.ttl_policy {
+            policy.clamp(&mut tdns_records);
+        }
+        tdns_records
+    }
+}
+
+// Parse the input file and turn it into Route53Records, bailing out if
+// anything goes wrong along the way
+fn route53_records(input: &Input, fwdzone: &str, ptrzone: &str)
+                   -> HashMap<String, Route53Record> {
+    // Process the input file into a Vec of TinyDNSRecords
+    let tdns_records = input.records();
+
+    // Process the TinyDNSRecords into Route53Records
+    match route53::generate(fwdzone, ptrzone, &tdns_records) {
+        Some(x) => x,
+        None => {
+            println!("Errors while generating Route53 Records");
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Build the Terraform provider backend a subcommand asks for, if it is a
+// provider subcommand at all.  Adding a provider only takes a module, an
+// entry here and its subcommand in cli.yml.
+fn provider_for(name: &str, flags: &ArgMatches) -> Option<Box<dyn Provider>> {
+    match name {
+        "route53" => {
+            // Required args, safe to unwrap
+            Some(Box::new(route53::Route53::new(flags.value_of("fwdzone").unwrap(),
+                                                flags.value_of("ptrzone").unwrap())))
+        },
+        "cloudflare" => {
+            Some(Box::new(cloudflare::Cloudflare::new(flags.value_of("fwdzone").unwrap(),
+                                                      flags.value_of("ptrzone").unwrap(),
+                                                      flags.is_present("proxied"))))
+        },
+        "google" => {
+            Some(Box::new(google::Google::new(flags.value_of("fwdzone").unwrap(),
+                                              flags.value_of("ptrzone").unwrap())))
+        },
+        "azure" => {
+            Some(Box::new(azure::Azure::new(flags.value_of("fwdzone").unwrap(),
+                                            flags.value_of("ptrzone").unwrap(),
+                                            flags.value_of("resource_group").unwrap())))
+        },
+        "cloudformation" => {
+            Some(Box::new(cloudformation::CloudFormation::new(flags.value_of("fwdzone").unwrap(),
+                                                              flags.value_of("ptrzone").unwrap(),
+                                                              flags.is_present("yaml"))))
+        },
+        _ => None
+    }
+}
+
+// Provider subcommands (route53, cloudflare, ...)
+// Parse the input and serialize it as the provider's Terraform JSON (or
+// whatever else the provider writes)
+fn provider_output(input: &Input, provider: &dyn Provider) -> String {
+    let tdns_records = input.records();
+    match provider.generate(&tdns_records) {
+        Some(x) => x,
+        None => {
+            println!("Errors while generating Terraform records");
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    }
+}
+
+// Subcommand: diff
+// Compare a TinyDNS file against previously generated Terraform JSON and
+// print what regenerating would change.  Exits 0 if nothing would, 2 if
+// there are differences (1 is already taken by errors).
+fn diff_output(input: &Input, diff_flags: &ArgMatches) -> ! {
+    let fwdzone = diff_flags.value_of("fwdzone").unwrap();
+    let ptrzone = diff_flags.value_of("ptrzone").unwrap();
+    let tffile = diff_flags.value_of("terraform").unwrap();
+
+    let new_records = route53_records(input, fwdzone, ptrzone);
+    let old_records = match route53::from_file(tffile) {
+        Some(x) => x,
+        None => {
+            println!("Errors while reading file: {}", tffile);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    };
+
+    let changes = diff::diff(&old_records, &new_records);
+    if diff_flags.is_present("json") {
+        match serde_json::to_string_pretty(&changes) {
+            Ok(x) => println!("{}", x),
+            Err(e) => {
+                println!("Error serializing JSON: {}", e);
                 std::process::exit(1);
             }
-        };
+        }
+    } else {
+        println!("{}", changes.to_text());
+    }
+
+    match changes.is_empty() {
+        true => std::process::exit(0),
+        false => std::process::exit(2)
+    }
+}
 
-        // Create a wrapper hashmap for the R53 Records and make a serializable
-        // struct for output
-        let mut outer_hash = HashMap::new();
-        outer_hash.insert("aws_route53_record".to_string(), r53_records);
-        let r53_file = Route53File { resource: outer_hash };
+// Subcommand: compare
+// Compare a TinyDNS file against a saved `aws route53
+// list-resource-record-sets` response.  Exit codes are as for diff.
+fn compare_output(input: &Input, cmp_flags: &ArgMatches) -> ! {
+    let export = cmp_flags.value_of("export").unwrap();
 
-        // Serialize it to a string using serde_json
-        let outstring = match serde_json::to_string_pretty(&r53_file) {
-            Ok(x) => x,
+    // Zone IDs don't take part in the comparison, so leave them empty
+    let tdns_records = route53_records(input, "", "");
+    let sets = match route53api::from_file(export) {
+        Some(x) => x,
+        None => {
+            println!("Errors while reading file: {}", export);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    };
+    let (r53_records, skipped) = route53api::to_route53_records(&sets);
+
+    let result = diff::compare(&tdns_records, &r53_records, skipped);
+    if cmp_flags.is_present("json") {
+        match serde_json::to_string_pretty(&result) {
+            Ok(x) => println!("{}", x),
             Err(e) => {
                 println!("Error serializing JSON: {}", e);
                 std::process::exit(1);
             }
-        };
+        }
+    } else {
+        println!("{}", result.to_text());
+    }
 
-        // If the -s flag was supplied, go ahead and print to STDOUT now
-        if matches.is_present("stdout") {
-            println!("{}", outstring);
+    match result.is_empty() {
+        true => std::process::exit(0),
+        false => std::process::exit(2)
+    }
+}
+
+// Subcommand: reverse
+// Report A/AAAA and PTR records that don't agree with each other.  Exit
+// codes are as for diff.
+fn reverse_output(input: &Input, rev_flags: &ArgMatches) -> ! {
+    let mut ranges = Vec::new();
+    for text in rev_flags.values_of("range").into_iter().flatten() {
+        match reverse::Range::parse(text) {
+            Ok(x) => ranges.push(x),
+            Err(e) => {
+                println!("Error parsing range {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let tdns_records = input.records();
+    let report = reverse::report(&tdns_records, &ranges);
+    if rev_flags.is_present("json") {
+        match serde_json::to_string_pretty(&report) {
+            Ok(x) => println!("{}", x),
+            Err(e) => {
+                println!("Error serializing JSON: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        println!("{}", report.to_text());
+    }
+
+    match report.is_empty() {
+        true => std::process::exit(0),
+        false => std::process::exit(2)
+    }
+}
+
+// Subcommand: tinydns
+// Read a Route53 Terraform JSON file back into a TinyDNS data file
+fn tinydns_output(infile: &str) -> String {
+    let r53_records = match route53::from_file(infile) {
+        Some(x) => x,
+        None => {
+            println!("Errors while reading file: {}", infile);
+            println!("Bailing out");
+            std::process::exit(1);
         }
+    };
+
+    let tdns_records = route53::to_tinydns(&r53_records);
+    let mut lines = tinydns::to_data(&tdns_records);
+    lines.push(String::new());
+    lines.join("\n")
+}
 
-        // If we're just linting the file, exit now.
-        if matches.is_present("lint") {
-            println!("No errors detected while processing {}", infile);
-            std::process::exit(0);
+// Subcommand: fetch
+// List every record set in a hosted zone through the Route53 API and write
+// them out as a TinyDNS data file.  Aliases and routing policy records
+// have no TinyDNS equivalent, so they're listed as '#' comments instead.
+fn fetch_output(fetch_flags: &ArgMatches) -> String {
+    let zone = fetch_flags.value_of("zone").unwrap();
+    let endpoint = fetch_flags.value_of("endpoint").unwrap_or(route53client::DEFAULT_ENDPOINT);
+    let credentials = match route53client::Credentials::from_env() {
+        Some(x) => x,
+        None => {
+            println!("AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY must be set to fetch records");
+            std::process::exit(1);
         }
+    };
+    let client = route53client::Client::new(endpoint, credentials);
+
+    let sets = match client.list_resource_record_sets(zone) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Error listing record sets in {}: {}", zone, e);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    };
+    let (tdns_records, mut lines) = route53api::to_tinydns(&sets);
+    lines.extend(tinydns::to_data(&tdns_records));
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+// Subcommand: bind
+// Write the input out as one BIND master file per zone
+fn bind_output(input: &Input) -> Vec<(String, String)> {
+    let tdns_records = input.records();
+    let zone_files = bind::to_zone_files(&tdns_records);
+    if zone_files.is_empty() {
+        println!("No zones found in {}, BIND output needs Z or . lines", input.file);
+        println!("Bailing out");
+        std::process::exit(1);
+    }
+    zone_files.into_iter().map(|(z, contents)| (format!("{}.zone", z), contents)).collect()
+}
+
+// Subcommand: octodns
+// Write the input out as one octoDNS zone YAML file per zone
+fn octodns_output(input: &Input) -> Vec<(String, String)> {
+    let tdns_records = input.records();
+    let zone_files = match octodns::to_zone_files(&tdns_records) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Error serializing YAML: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if zone_files.is_empty() {
+        println!("No zones found in {}, octoDNS output needs Z or . lines", input.file);
+        println!("Bailing out");
+        std::process::exit(1);
+    }
+    zone_files.into_iter().map(|(z, contents)| (format!("{}.yaml", z), contents)).collect()
+}
+
+// Subcommand: dnscontrol
+// Write the input out as a DNSControl dnsconfig.js
+fn dnscontrol_output(input: &Input, dc_flags: &ArgMatches) -> String {
+    // Has a default, safe to unwrap
+    let provider = dc_flags.value_of("provider").unwrap();
+    let tdns_records = input.records();
+    match dnscontrol::to_dnsconfig(&tdns_records, provider) {
+        Some(x) => x,
+        None => {
+            println!("No zones found in {}, DNSControl output needs Z or . lines", input.file);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    }
+}
 
-        // Dump the serialized JSON string to our output file
-        let mut ofile_writer = BufWriter::new(ofile);
-        match ofile_writer.write_all(outstring.as_bytes()) {
-            Ok(_) => {},
+// Work out the change batches for each hosted zone: an UPSERT for every
+// record set or, given an older version of the input, just the changes
+// from it.  Either way they're split up to fit Route53's limits.
+fn zone_batches(input: &Input, flags: &ArgMatches) -> Vec<(String, Vec<route53api::ChangeBatch>)> {
+    let fwdzone = flags.value_of("fwdzone").unwrap();
+    let ptrzone = flags.value_of("ptrzone").unwrap();
+
+    let r53_records = route53_records(input, fwdzone, ptrzone);
+    let zone_changes = match flags.value_of("old") {
+        Some(oldfile) => {
+            // The old file is read the same way as the input
+            let old_input = Input { file: oldfile, ..*input };
+            let old_records = route53_records(&old_input, fwdzone, ptrzone);
+            route53api::changes(&old_records, &r53_records)
+        },
+        None => route53api::upserts(&r53_records)
+    };
+    if zone_changes.is_empty() {
+        println!("No changes to make");
+    }
+
+    let mut retval = Vec::new();
+    for (zone, changes) in zone_changes {
+        match route53api::to_change_batches(changes) {
+            Ok(x) => retval.push((zone, x)),
             Err(e) => {
-                println!("Error writing file {}: {}", &outfile, e);
+                println!("Error building change batches for {}: {}", zone, e);
+                println!("Bailing out");
                 std::process::exit(1);
             }
         }
     }
+    retval
+}
 
-    // Complete
-    println!("Successfully processed {} and wrote {}", infile, outfile);
+// Subcommand: changebatch
+// Write the change batches out as ChangeResourceRecordSets request bodies.
+// Each hosted zone gets its own numbered files, e.g. Z123-1.json,
+// Z123-2.json...
+fn changebatch_output(input: &Input, cb_flags: &ArgMatches) -> Vec<(String, String)> {
+    let mut retval = Vec::new();
+    for (zone, batches) in zone_batches(input, cb_flags) {
+        for (i, batch) in batches.iter().enumerate() {
+            match serde_json::to_string_pretty(batch) {
+                Ok(x) => retval.push((format!("{}-{}.json", zone, i + 1), x)),
+                Err(e) => {
+                    println!("Error serializing JSON: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    retval
 }
 
+// Subcommand: apply
+// Send the change batches straight to the Route53 API and wait for each to
+// be INSYNC before sending the next.  With --dry-run the requests are only
+// printed.  Exits 0 once everything is applied, 1 on any error.
+fn apply_output(input: &Input, apply_flags: &ArgMatches) -> ! {
+    let endpoint = apply_flags.value_of("endpoint").unwrap_or(route53client::DEFAULT_ENDPOINT);
+    let timeout = match value_t!(apply_flags, "timeout", u64) {
+        Ok(x) => Duration::from_secs(x),
+        Err(e) => {
+            println!("Bad timeout: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let zones = zone_batches(input, apply_flags);
+
+    if apply_flags.is_present("dry_run") {
+        for (zone, batches) in &zones {
+            for batch in batches {
+                println!("{}", route53client::change_request(zone, batch).to_text(endpoint));
+            }
+        }
+        std::process::exit(0);
+    }
+
+    let credentials = match route53client::Credentials::from_env() {
+        Some(x) => x,
+        None => {
+            println!("AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY must be set to apply changes");
+            std::process::exit(1);
+        }
+    };
+    let client = route53client::Client::new(endpoint, credentials);
+
+    for (zone, batches) in &zones {
+        for (i, batch) in batches.iter().enumerate() {
+            let info = match client.change_resource_record_sets(zone, batch) {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Error applying batch {} of {} to {}: {}", i + 1, batches.len(), zone, e);
+                    std::process::exit(1);
+                }
+            };
+            println!("Submitted batch {} of {} to {} ({} changes): change {} {}",
+                     i + 1, batches.len(), zone, batch.changes.len(), info.id, info.status);
+            match client.wait_for_change(&info.id, Duration::from_secs(5), timeout) {
+                Ok(x) => println!("Change {} is {}", x.id, x.status),
+                Err(e) => {
+                    println!("Error waiting for change {}: {}", info.id, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+    println!("Successfully applied {}", input.file);
+    std::process::exit(0);
+}
+
+// Create the destination directory and file, then dump the output into it
+fn write_output(outdir: &str, outfile: &str, outstring: &str) {
+    match std::fs::create_dir_all(outdir) {
+        Ok(_) => {},
+        Err(e) => {
+            println!("Error creating directory {}: {}", outdir, e);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    };
+
+    let ofile = match File::create(outfile) {
+        Ok(f) => f,
+        Err(e) => {
+            println!("Error creating output file {}: {}", outfile, e);
+            println!("Bailing out");
+            std::process::exit(1);
+        }
+    };
+
+    let mut ofile_writer = BufWriter::new(ofile);
+    match ofile_writer.write_all(outstring.as_bytes()) {
+        Ok(_) => {},
+        Err(e) => {
+            println!("Error writing file {}: {}", outfile, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Define functions for writing TinyDNSRecords out as octoDNS zone YAML, one
+// file per zone, keyed by names relative to the zone.
+use std::cmp::Ordering;
+use serde_yaml::{self, Mapping, Value};
+use types::TinyDNSRecord;
+use zone::{self, absolute};
+
+// A record set being built up: type, TTL and values in the order given
+struct RecordSet {
+    rtype: String,
+    ttl: u32,
+    targets: Vec<String>
+}
+
+// Compare names the way octoDNS's natural sort does, so runs of digits are
+// compared as numbers ("host2" before "host10").  octoDNS refuses to load
+// files whose keys aren't in this order.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let chunks = |s: &str| {
+        let mut retval: Vec<String> = Vec::new();
+        for c in s.chars() {
+            match retval.last_mut() {
+                Some(ref mut last) if last.chars().all(|x| x.is_ascii_digit()) ==
+                                      c.is_ascii_digit() => last.push(c),
+                _ => retval.push(c.to_string())
+            }
+        }
+        retval
+    };
+    let (ca, cb) = (chunks(a), chunks(b));
+    for (x, y) in ca.iter().zip(cb.iter()) {
+        let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(m), Ok(n)) => m.cmp(&n).then(x.cmp(y)),
+            _ => x.cmp(y)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    ca.len().cmp(&cb.len())
+}
+
+fn string(text: &str) -> Value {
+    Value::String(text.to_string())
+}
+
+// Numbers are written as numbers where they parse, as-is otherwise
+fn number(text: &str) -> Value {
+    match text.parse::<u64>() {
+        Ok(x) => Value::Number(x.into()),
+        Err(_) => string(text)
+    }
+}
+
+// Build a mapping from (key, value) pairs, which callers give in sorted
+// order
+fn mapping(pairs: Vec<(&str, Value)>) -> Value {
+    let mut m = Mapping::new();
+    for (k, v) in pairs {
+        m.insert(string(k), v);
+    }
+    Value::Mapping(m)
+}
+
+// Turn one target into octoDNS's value for its record type.  Hostnames get
+// their trailing dot and semicolons in text are escaped, as octoDNS wants.
+fn value(rtype: &str, target: &str) -> Value {
+    let fields: Vec<&str> = target.split_whitespace().collect();
+    match rtype {
+        "CNAME" | "NS" | "PTR" => string(&absolute(target)),
+        "TXT" | "SPF" => string(&target.replace(';', "\\;")),
+        "MX" if fields.len() == 2 => mapping(vec![("exchange", string(&absolute(fields[1]))),
+                                                  ("preference", number(fields[0]))]),
+        "SRV" if fields.len() == 4 => mapping(vec![("port", number(fields[2])),
+                                                   ("priority", number(fields[0])),
+                                                   ("target", string(&absolute(fields[3]))),
+                                                   ("weight", number(fields[1]))]),
+        "CAA" if fields.len() >= 3 => {
+            let caa_value = target.splitn(3, ' ').nth(2).unwrap_or("");
+            mapping(vec![("flags", number(fields[0])), ("tag", string(fields[1])),
+                         ("value", string(caa_value))])
+        },
+        _ => string(target)
+    }
+}
+
+// Render a record set as an octoDNS record: 'value' for one, 'values' for
+// several
+fn record(set: &RecordSet) -> Value {
+    let mut values: Vec<Value> = set.targets.iter().map(|t| value(&set.rtype, t)).collect();
+    let v = match values.len() {
+        1 => ("value", values.remove(0)),
+        _ => ("values", Value::Sequence(values))
+    };
+    mapping(vec![("ttl", Value::Number(i64::from(set.ttl).into())),
+                 ("type", string(&set.rtype)), v])
+}
+
+// Given a Vec of TinyDNSRecords, produce (zone name, YAML text) pairs, one
+// per zone with an SOA.  octoDNS manages the SOA itself so it isn't
+// written.  Records with the same name and type become one record; if
+// their TTLs disagree the lowest wins.  Records outside every zone are
+// skipped with a warning.
+pub fn to_zone_files(tdrs: &[TinyDNSRecord]) -> Result<Vec<(String, String)>, String> {
+    let (zones, leftovers) = zone::split_by_zone(tdrs);
+    let mut retval = Vec::new();
+
+    for rec in leftovers {
+        println!("Warning: skipping {} record for {}, it is not in any zone with an SOA",
+                 rec.rtype, rec.fqdn);
+    }
+
+    for (zname, recs) in zones {
+        let mut names: Vec<(String, Vec<RecordSet>)> = Vec::new();
+        for rec in recs.iter().filter(|r| r.rtype != "SOA") {
+            let rel = zone::relative_name(&rec.fqdn, &zname).to_lowercase();
+            let idx = match names.iter().position(|(n, _)| *n == rel) {
+                Some(x) => x,
+                None => {
+                    names.push((rel.to_string(), Vec::new()));
+                    names.len() - 1
+                }
+            };
+            let sets = &mut names[idx].1;
+            match sets.iter_mut().find(|s| s.rtype == rec.rtype) {
+                Some(set) => {
+                    if set.ttl != rec.ttl {
+                        println!("Warning: {} records for {} have different TTLs, using the lowest",
+                                 rec.rtype, rec.fqdn);
+                        set.ttl = set.ttl.min(rec.ttl);
+                    }
+                    set.targets.push(rec.target.to_string());
+                },
+                None => sets.push(RecordSet {
+                    rtype:   rec.rtype.to_string(),
+                    ttl:     rec.ttl,
+                    targets: vec![rec.target.to_string()]
+                })
+            }
+        }
+
+        names.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+        let mut doc = Mapping::new();
+        for (name, mut sets) in names {
+            sets.sort_by(|a, b| a.rtype.cmp(&b.rtype));
+            let entry = match sets.len() {
+                1 => record(&sets[0]),
+                _ => Value::Sequence(sets.iter().map(record).collect())
+            };
+            doc.insert(string(&name), entry);
+        }
+
+        let text = serde_yaml::to_string(&Value::Mapping(doc)).map_err(|e| e.to_string())?;
+        retval.push((zname, text));
+    }
+
+    Ok(retval)
+}
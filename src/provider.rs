@@ -0,0 +1,125 @@
+// Define the trait Terraform DNS provider backends implement, along with
+// the record-set grouping they share.  Each provider lives in its own
+// module and is selected by its own subcommand.
+use std::collections::HashMap;
+use types::{TinyDNSRecord, Route53Record};
+
+pub trait Provider {
+    // Which zone a record belongs to, in whatever form the provider's
+    // resources refer to zones (an ID, a Terraform reference, a name...)
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String;
+
+    // Terraform resource name for a record set of the given type and name
+    fn resource_name(&self, rtype: &str, name: &str) -> String {
+        resource_name(rtype, name)
+    }
+
+    // Merge TinyDNSRecords into record sets keyed by resource name.  Most
+    // providers will want the default, shared behaviour.
+    fn group(&self, tdrs: &[TinyDNSRecord]) -> Option<HashMap<String, Route53Record>> {
+        group(self, tdrs)
+    }
+
+    // Default output directory and file name for the serialised output
+    fn output_dir(&self) -> &'static str {
+        "terraform"
+    }
+
+    fn output_name(&self, basename: &str) -> String {
+        format!("{}.tf", basename)
+    }
+
+    // Serialise grouped record sets into the provider's Terraform JSON
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String>;
+
+    // Group and serialise in one go, printing any errors
+    fn generate(&self, tdrs: &[TinyDNSRecord]) -> Option<String> {
+        let sets = self.group(tdrs)?;
+        match self.serialise(sets) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                println!("Error serializing output: {}", e);
+                None
+            }
+        }
+    }
+}
+
+// Turn a record type and name into a Terraform-friendly resource name,
+// e.g. "A" and "foo.example.com." become "a-foo-example-com"
+pub fn resource_name(rtype: &str, name: &str) -> String {
+    let record_name = format!("{}.{}", rtype, name);
+
+    // If there's a period at the end we should get rid of it before 
+    // converting the name into something AWS-compatable
+    record_name.trim_end_matches('.').replace(".", "-").to_lowercase()
+}
+
+// Names of the zone apexes among grouped record sets, i.e. wherever there's
+// an SOA.  Hosted DNS services tend to manage the SOA and apex NS records
+// themselves, so providers use this to leave them out.
+pub fn zone_apexes(sets: &HashMap<String, Route53Record>) -> Vec<String> {
+    sets.values()
+        .filter(|s| s.rtype == "SOA")
+        .map(|s| s.name.trim_end_matches('.').to_lowercase())
+        .collect()
+}
+
+// Is this record set one the DNS service manages itself?
+pub fn service_managed(set: &Route53Record, apexes: &[String]) -> bool {
+    let name = set.name.trim_end_matches('.').to_lowercase();
+    set.rtype == "SOA" || (set.rtype == "NS" && apexes.contains(&name))
+}
+
+// Given a provider and a Vec of TinyDNSRecords, construct a HashMap of
+// record sets with resource names as the keys.  Records with the same type
+// and name are merged into one set.
+pub fn group<P: Provider + ?Sized>(provider: &P, tdrs: &[TinyDNSRecord])
+                                   -> Option<HashMap<String, Route53Record>> {
+    // Init return hashmap
+    let mut retval: HashMap<String, Route53Record> = HashMap::new();
+
+    // Watch for any errors
+    let mut error_flag = false;
+
+    // Iterate through the tdrs vector, creating a single Route53Record each
+    for rec in tdrs {
+        // Generate the record in whichever zone the provider says
+        let mut r53r = Route53Record::new(&provider.zone_for(rec), &rec.fqdn,
+                                          &rec.rtype, &rec.target, rec.ttl);
+
+        // Create a string representing a name to use as a hash key
+        let record_name = provider.resource_name(&r53r.rtype, &r53r.name);
+
+        // Check for an existing matching key in the hashmap and merge the
+        // record structs if one is found
+        if let Some(old_record) = retval.remove(&record_name) {
+            // If these are both PTR records, we got a problem here.
+            if r53r.rtype.as_str() == "PTR" {
+                println!("Error: Found two PTR records for the same IP!");
+                println!("PTR: {}", &r53r.name);
+                println!("FQDNs: {}, {}", &r53r.records[0], &old_record.records[0]);
+                error_flag = true;
+            } else {
+                // Otherwise go ahead and try to merge them
+                // This used to return None early but I want to try to process
+                // everything in the files before bailing out in case there
+                // are multiple issues
+                if !r53r.merge(&old_record) {
+                    println!("Error merging records:");
+                    println!("Old: {:?}\nNew: {:?}", old_record, r53r);
+                    error_flag = true;
+                }
+            }
+        }
+
+        // Insert R53Record and name-key into the hashmap
+        retval.insert(record_name, r53r);
+    }
+
+    // Return the hashmap if there were no errors, None otherwise
+    match error_flag {
+        true => None,
+        false => Some(retval)
+    }
+}
@@ -0,0 +1,242 @@
+// Define a report cross-referencing A/AAAA records against PTRs, since the
+// two are only kept in step by convention (or by '=' lines).
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use types::TinyDNSRecord;
+use zone;
+
+// A block of addresses we keep PTRs for, as an address and prefix length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub addr: IpAddr,
+    pub prefix: u8
+}
+
+impl Range {
+    // Parse CIDR notation; a bare address is a range of one
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (addr, prefix) = match text.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (text, None)
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|e| format!("{}: {}", text, e))?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128
+        };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().map_err(|e| format!("{}: {}", text, e))?,
+            None => max
+        };
+        if prefix > max {
+            return Err(format!("{}: prefix longer than {} bits", text, max));
+        }
+        Ok(Range { addr, prefix })
+    }
+
+    // The range a reverse zone covers, if it's an in-addr.arpa or ip6.arpa
+    // zone on a whole label boundary
+    pub fn from_zone(name: &str) -> Option<Self> {
+        parse_arpa(name).map(|(addr, prefix)| Range { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            },
+            _ => false
+        }
+    }
+}
+
+// Turn an in-addr.arpa or ip6.arpa name into the address it stands for
+// (zero filled if the name is only part of one) and how many bits of it
+// the name gives
+fn parse_arpa(name: &str) -> Option<(IpAddr, u8)> {
+    let name = zone::normalise(name);
+    if let Some(rest) = name.strip_suffix("in-addr.arpa") {
+        let labels: Vec<&str> = rest.split('.').filter(|l| !l.is_empty()).collect();
+        if labels.len() > 4 {
+            return None;
+        }
+        let mut octets = [0u8; 4];
+        for (i, label) in labels.iter().rev().enumerate() {
+            octets[i] = label.parse::<u8>().ok()?;
+        }
+        return Some((IpAddr::V4(Ipv4Addr::from(octets)), labels.len() as u8 * 8));
+    }
+    if let Some(rest) = name.strip_suffix("ip6.arpa") {
+        let labels: Vec<&str> = rest.split('.').filter(|l| !l.is_empty()).collect();
+        if labels.len() > 32 {
+            return None;
+        }
+        let mut bits: u128 = 0;
+        for (i, label) in labels.iter().rev().enumerate() {
+            if label.len() != 1 {
+                return None;
+            }
+            let nibble = u128::from_str_radix(label, 16).ok()?;
+            bits |= nibble << (124 - 4 * i);
+        }
+        return Some((IpAddr::V6(Ipv6Addr::from(bits)), labels.len() as u8 * 4));
+    }
+    None
+}
+
+// The address a PTR record's name stands for, if it's a full one
+pub fn ptr_address(name: &str) -> Option<IpAddr> {
+    match parse_arpa(name) {
+        Some((addr, 32)) | Some((addr, 128)) => Some(addr),
+        _ => None
+    }
+}
+
+// One address and name that should point at each other but don't
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Mismatch {
+    pub address: String,
+    pub name: String,
+    pub lines: Vec<usize>
+}
+
+// An address with PTRs to more than one name
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Conflict {
+    pub address: String,
+    pub names: Vec<String>,
+    pub lines: Vec<usize>
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Report {
+    // PTRs whose target has no A/AAAA record back to the same address
+    pub ptr_without_a: Vec<Mismatch>,
+    // A/AAAA records in one of the reverse ranges with no PTR at all
+    pub a_without_ptr: Vec<Mismatch>,
+    pub conflicting_ptrs: Vec<Conflict>,
+    // PTRs whose names aren't a full reverse address, so can't be checked
+    pub skipped: Vec<String>
+}
+
+// Lines of the input file the records came from.  Records from a BIND
+// $INCLUDE are left out, as their lines are in another file.
+fn lines(recs: &[&TinyDNSRecord]) -> Vec<usize> {
+    let mut retval: Vec<usize> = recs.iter().filter(|r| r.source.is_none())
+        .map(|r| r.line).filter(|l| *l > 0).collect();
+    retval.sort();
+    retval.dedup();
+    retval
+}
+
+// ' (line 3)', ' (lines 3, 4)' or nothing if the records weren't read from
+// a file
+fn lines_text(lines: &[usize]) -> String {
+    let text: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    match text.len() {
+        0 => String::new(),
+        1 => format!(" (line {})", text[0]),
+        _ => format!(" (lines {})", text.join(", "))
+    }
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.ptr_without_a.is_empty() && self.a_without_ptr.is_empty() &&
+            self.conflicting_ptrs.is_empty()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        for m in &self.ptr_without_a {
+            lines.push(format!("PTR without matching A: {} -> {}{}", m.address, m.name,
+                               lines_text(&m.lines)));
+        }
+        for m in &self.a_without_ptr {
+            lines.push(format!("A without PTR: {} -> {}{}", m.name, m.address,
+                               lines_text(&m.lines)));
+        }
+        for c in &self.conflicting_ptrs {
+            lines.push(format!("Conflicting PTRs: {} -> {}{}", c.address, c.names.join(", "),
+                               lines_text(&c.lines)));
+        }
+        for s in &self.skipped {
+            lines.push(format!("Skipped: {}", s));
+        }
+        lines.push(format!("{} PTR without A, {} A without PTR, {} conflicting, {} skipped",
+                           self.ptr_without_a.len(), self.a_without_ptr.len(),
+                           self.conflicting_ptrs.len(), self.skipped.len()));
+        lines.join("\n")
+    }
+}
+
+// Cross-reference the address and PTR records in 'tdrs'.  A/AAAA records
+// only need a PTR if their address is in one of 'ranges'; with no ranges
+// given, the in-addr.arpa and ip6.arpa zones in the data are used instead.
+pub fn report(tdrs: &[TinyDNSRecord], ranges: &[Range]) -> Report {
+    let ranges: Vec<Range> = match ranges.is_empty() {
+        true => zone::find_zones(tdrs).iter().filter_map(|z| Range::from_zone(z)).collect(),
+        false => ranges.to_vec()
+    };
+    let mut retval = Report::default();
+
+    // Address records by (address, name), PTRs by address
+    let mut addrs: BTreeMap<(IpAddr, String), Vec<&TinyDNSRecord>> = BTreeMap::new();
+    let mut ptrs: BTreeMap<IpAddr, Vec<&TinyDNSRecord>> = BTreeMap::new();
+    for rec in tdrs {
+        match rec.rtype.as_str() {
+            "A" | "AAAA" => {
+                if let Ok(ip) = rec.target.parse::<IpAddr>() {
+                    addrs.entry((ip, zone::normalise(&rec.fqdn))).or_default().push(rec);
+                }
+            },
+            "PTR" => match ptr_address(&rec.fqdn) {
+                Some(ip) => ptrs.entry(ip).or_default().push(rec),
+                None => retval.skipped.push(format!("PTR for {}, which isn't a reverse address",
+                                                    rec.fqdn))
+            },
+            _ => {}
+        }
+    }
+
+    for (ip, recs) in &ptrs {
+        let mut names: Vec<String> = recs.iter().map(|r| zone::normalise(&r.target)).collect();
+        names.sort();
+        names.dedup();
+        for name in &names {
+            if !addrs.contains_key(&(*ip, name.to_string())) {
+                let from: Vec<&TinyDNSRecord> = recs.iter()
+                    .filter(|r| zone::normalise(&r.target) == *name).cloned().collect();
+                retval.ptr_without_a.push(Mismatch {
+                    address: ip.to_string(),
+                    name:    name.to_string(),
+                    lines:   lines(&from)
+                });
+            }
+        }
+        if names.len() > 1 {
+            retval.conflicting_ptrs.push(Conflict {
+                address: ip.to_string(),
+                names,
+                lines:   lines(recs)
+            });
+        }
+    }
+
+    for ((ip, name), recs) in &addrs {
+        if ranges.iter().any(|r| r.contains(ip)) && !ptrs.contains_key(ip) {
+            retval.a_without_ptr.push(Mismatch {
+                address: ip.to_string(),
+                name:    name.to_string(),
+                lines:   lines(recs)
+            });
+        }
+    }
+
+    retval
+}
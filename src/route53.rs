@@ -1,77 +1,105 @@
 // Define structs and functions for generating Route53-specific Terraform
 // output using TinyDNSRecords.
 use std::collections::HashMap;
-use types::{TinyDNSRecord, Route53Record};
+use std::fs::File;
+use std::io::BufReader;
+use serde_json;
+use types::{TinyDNSRecord, Route53Record, Route53File};
+use provider::{self, Provider};
 
-// Given a Vec of TinyDNSRecords, a forward zone ID, and a ptr zone ID,
-// construct a HashMap of Route53Records with names as the keys.
-pub fn generate(fzone: &str, rzone: &str, tdrs: &Vec<TinyDNSRecord>) 
-               -> Option<HashMap<String, Route53Record>> {
-    // Init return hashmap
-    let mut retval: HashMap<String, Route53Record> = HashMap::new();
+// Route53 provider: PTRs go in the reverse zone, everything else in the
+// forward zone, and record sets are written out as aws_route53_record
+// resources.
+pub struct Route53 {
+    pub fwdzone: String,
+    pub ptrzone: String
+}
 
-    // Watch for any errors
-    let mut error_flag = false;
+impl Route53 {
+    pub fn new(fwdzone: &str, ptrzone: &str) -> Self {
+        Route53 {
+            fwdzone: fwdzone.to_string(),
+            ptrzone: ptrzone.to_string()
+        }
+    }
+}
 
-    // Iterate through the tdrs vector, creating a single Route53Record each
-    for rec in tdrs {
-        // Decide which zone_id to set based on record type.  PTRs get the
-        // reverse zone, everything else gets the forward zone.
-        let zoneid = match rec.rtype.as_str() {
-            "PTR" => &rzone,
-            _     => &fzone
-        };
+impl Provider for Route53 {
+    // Decide which zone_id to set based on record type.  PTRs get the
+    // reverse zone, everything else gets the forward zone.
+    fn zone_for(&self, rec: &TinyDNSRecord) -> String {
+        match rec.rtype.as_str() {
+            "PTR" => self.ptrzone.to_string(),
+            _     => self.fwdzone.to_string()
+        }
+    }
 
-        // Generate the record.
-        let rec_vec = vec![rec.target.to_string()];
-        let mut r53r = Route53Record {
-            zone_id:  zoneid.to_string(),
-            name:     rec.fqdn.to_string(),
-            rtype:    rec.rtype.to_string(),
-            records:  rec_vec,
-            ttl:      rec.ttl
-        };
+    // Create a wrapper hashmap for the R53 Records and make a serializable
+    // struct for output
+    fn serialise(&self, sets: HashMap<String, Route53Record>) -> Result<String, String> {
+        let mut outer_hash = HashMap::new();
+        outer_hash.insert("aws_route53_record".to_string(), sets);
+        let r53_file = Route53File { resource: outer_hash };
+        serde_json::to_string_pretty(&r53_file).map_err(|e| e.to_string())
+    }
+}
 
-        // Create a string representing a name to use as a hash key
-        let mut record_name = format!("{}.{}", &r53r.rtype, &r53r.name);
+// Given a Vec of TinyDNSRecords, a forward zone ID, and a ptr zone ID,
+// construct a HashMap of Route53Records with names as the keys.
+pub fn generate(fzone: &str, rzone: &str, tdrs: &[TinyDNSRecord])
+               -> Option<HashMap<String, Route53Record>> {
+    provider::group(&Route53::new(fzone, rzone), tdrs)
+}
 
-        // If there's a period at the end we should get rid of it before 
-        // converting the name into something AWS-compatable
-        record_name = record_name.trim_end_matches('.').to_string();
-        record_name = record_name.replace(".", "-").to_lowercase();
+// Given the filename of a previously generated Terraform JSON file, read it
+// back in and return the aws_route53_record resources it contains.
+pub fn from_file(fname: &str) -> Option<HashMap<String, Route53Record>> {
+    let f = match File::open(fname) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Error opening file {}: {}", fname, e);
+            return None;
+        }
+    };
 
-        // Check for an existing matching key in the hashmap and merge the
-        // record structs if one is found
-        if retval.contains_key(&record_name) {
-            // Unwrap should be safe since we wouldn't be here otherwise
-            let old_record = retval.remove(&record_name).unwrap();
-            // If these are both PTR records, we got a problem here.
-            if r53r.rtype.as_str() == "PTR" {
-                println!("Error: Found two PTR records for the same IP!");
-                println!("PTR: {}", &r53r.name);
-                println!("FQDNs: {}, {}", &r53r.records[0], &old_record.records[0]);
-                error_flag = true;
-            } else {
-                // Otherwise go ahead and try to merge them
-                // This used to return None early but I want to try to process
-                // everything in the files before bailing out in case there 
-                // are multiple issues
-                if !r53r.merge(&old_record) {
-                    println!("Error merging records:");
-                    println!("Old: {:?}\nNew: {:?}", old_record, r53r);
-                    error_flag = true;
-                }
-            }
+    let mut r53_file: Route53File = match serde_json::from_reader(BufReader::new(f)) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Error reading Terraform JSON from {}: {}", fname, e);
+            return None;
         }
+    };
 
-        // Insert R53Record and name-key into the hashmap
-        retval.insert(record_name, r53r);
+    match r53_file.resource.remove("aws_route53_record") {
+        Some(x) => Some(x),
+        None => {
+            println!("No aws_route53_record resources found in {}", fname);
+            None
+        }
     }
+}
+
+// Given a HashMap of Route53Records, break each record set back down into
+// one TinyDNSRecord per value.  Resources are visited in name order so the
+// output is stable from run to run.
+pub fn to_tinydns(records: &HashMap<String, Route53Record>) -> Vec<TinyDNSRecord> {
+    let mut retval = Vec::new();
+    let mut keys: Vec<&String> = records.keys().collect();
+    keys.sort();
 
-    // Return the hashmap if there were no errors, None otherwise
-    match error_flag {
-        true => None,
-        false => Some(retval)
+    for key in keys {
+        let r53r = &records[key];
+        for value in &r53r.records {
+            retval.push(TinyDNSRecord {
+                rtype:  r53r.rtype.to_string(),
+                fqdn:   r53r.name.to_string(),
+                target: value.to_string(),
+                ttl:    r53r.ttl,
+                line:   0,
+                source: None
+            });
+        }
     }
-}
 
+    retval
+}
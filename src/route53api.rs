@@ -0,0 +1,383 @@
+// Define structs and functions for dealing with record sets in the shape
+// the Route53 API itself uses, e.g. the JSON written out by
+// `aws route53 list-resource-record-sets`.
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use serde_json;
+use types::{Route53Record, TinyDNSRecord};
+use provider;
+use route53;
+use tinydns;
+use diff;
+use bind;
+use zone::absolute;
+
+// Per-request limits on ChangeResourceRecordSets.  UPSERTs count double
+// towards both.
+pub const MAX_BATCH_RECORDS: usize = 1000;
+pub const MAX_BATCH_CHARS: usize = 32000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct ResourceRecordSet {
+    pub name: String,
+    #[serde(rename="Type")]
+    pub rtype: String,
+    #[serde(rename="TTL", default, skip_serializing_if="Option::is_none")]
+    pub ttl: Option<i64>,
+    #[serde(default, skip_serializing_if="Vec::is_empty")]
+    pub resource_records: Vec<ResourceRecord>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub alias_target: Option<AliasTarget>,
+    // Routing policy settings.  tinydns has no way to express any of these,
+    // so they're only carried along to be reported.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub set_identifier: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub weight: Option<i64>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub failover: Option<String>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub geo_location: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub multi_value_answer: Option<bool>,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub health_check_id: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct ResourceRecord {
+    pub value: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct AliasTarget {
+    pub hosted_zone_id: String,
+    #[serde(rename="DNSName")]
+    pub dns_name: String,
+    pub evaluate_target_health: bool
+}
+
+// The parts of a ListResourceRecordSets response we care about
+#[derive(Deserialize, Debug)]
+#[serde(rename_all="PascalCase")]
+pub struct ListResourceRecordSetsResponse {
+    pub resource_record_sets: Vec<ResourceRecordSet>
+}
+
+// A ChangeResourceRecordSets request body, as taken by
+// `aws route53 change-resource-record-sets --change-batch`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct ChangeBatch {
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub comment: Option<String>,
+    pub changes: Vec<Change>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all="PascalCase")]
+pub struct Change {
+    pub action: String,
+    pub resource_record_set: ResourceRecordSet
+}
+
+impl Change {
+    pub fn new(action: &str, set: ResourceRecordSet) -> Self {
+        Change { action: action.to_string(), resource_record_set: set }
+    }
+
+    // How much this change counts towards a request's record and character
+    // limits
+    pub fn weight(&self) -> (usize, usize) {
+        let records = &self.resource_record_set.resource_records;
+        let chars: usize = records.iter().map(|r| r.value.len()).sum();
+        match self.action.as_str() {
+            "UPSERT" => (records.len() * 2, chars * 2),
+            _ => (records.len(), chars)
+        }
+    }
+}
+
+impl ResourceRecordSet {
+    // Describe why this set can't be expressed as plain records, if it can't
+    pub fn unsupported(&self) -> Option<String> {
+        if let Some(ref alias) = self.alias_target {
+            return Some(format!("alias to {}", alias.dns_name));
+        }
+        if self.set_identifier.is_some() || self.weight.is_some() ||
+            self.region.is_some() || self.failover.is_some() ||
+            self.geo_location.is_some() || self.multi_value_answer.is_some() {
+            return Some(format!("routing policy (set identifier {})",
+                                self.set_identifier.as_ref().map_or("none", |x| x.as_str())));
+        }
+        None
+    }
+}
+
+// Given the filename of a saved list-resource-record-sets response, read
+// in the record sets it contains
+pub fn from_file(fname: &str) -> Option<Vec<ResourceRecordSet>> {
+    let f = match File::open(fname) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("Error opening file {}: {}", fname, e);
+            return None;
+        }
+    };
+
+    let response: ListResourceRecordSetsResponse = match serde_json::from_reader(BufReader::new(f)) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Error reading Route53 record sets from {}: {}", fname, e);
+            return None;
+        }
+    };
+
+    Some(response.resource_record_sets)
+}
+
+// Route53 hands back TXT values (and CAA values) as one or more quoted
+// strings, e.g. "\"v=spf1 -all\"" or "\"part one\" \"part two\"".  Join
+// them back up into the single unquoted string tinydns deals in, decoding
+// \NNN octal escapes (as quote_txt writes them) back into bytes.
+pub fn unquote_txt(value: &str) -> String {
+    if !value.starts_with('"') {
+        return value.to_string();
+    }
+    let bytes = value.as_bytes();
+    let mut out = Vec::new();
+    let mut in_quotes = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => in_quotes = !in_quotes,
+            b'\\' if in_quotes => {
+                match bytes.get(i + 1..i + 4).and_then(octal) {
+                    Some(n) => {
+                        out.push(n);
+                        i += 3;
+                    },
+                    None => if let Some(n) = bytes.get(i + 1) {
+                        out.push(*n);
+                        i += 1;
+                    }
+                }
+            },
+            b if in_quotes => out.push(b),
+            _ => {}
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// The byte three octal digits stand for, if they are octal digits and
+// the number fits
+fn octal(digits: &[u8]) -> Option<u8> {
+    if !digits.iter().all(|b| (b'0'..=b'7').contains(b)) {
+        return None;
+    }
+    let n = digits.iter().fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+    match n <= 255 {
+        true => Some(n as u8),
+        false => None
+    }
+}
+
+// Quote a TXT value the way Route53 expects, the opposite of unquote_txt:
+// split into 255 character strings, with octal escapes
+pub fn quote_txt(text: &str) -> String {
+    bind::quote_strings(text, bind::Escape::Octal)
+}
+
+// Turn a Route53Record into the API's ResourceRecordSet, with a fully
+// qualified name and TXT and CAA values quoted
+pub fn to_resource_record_set(rec: &Route53Record) -> ResourceRecordSet {
+    let resource_records = rec.records.iter().map(|v| {
+        let value = match rec.rtype.as_str() {
+            "TXT" | "SPF" => quote_txt(v),
+            "CAA" => {
+                let fields: Vec<&str> = v.splitn(3, ' ').collect();
+                match fields.len() {
+                    3 => format!("{} {} {}", fields[0], fields[1], quote_txt(fields[2])),
+                    _ => v.to_string()
+                }
+            },
+            _ => v.to_string()
+        };
+        ResourceRecord { value }
+    }).collect();
+
+    ResourceRecordSet {
+        name:               absolute(&rec.name),
+        rtype:              rec.rtype.to_string(),
+        ttl:                Some(i64::from(rec.ttl)),
+        resource_records,
+        alias_target:       None,
+        set_identifier:     None,
+        weight:             None,
+        region:             None,
+        failover:           None,
+        geo_location:       None,
+        multi_value_answer: None,
+        health_check_id:    None
+    }
+}
+
+// The record sets Route53 doesn't manage itself.  Every hosted zone comes
+// with its own SOA and apex NS records, and sending ours would overwrite
+// them (and the SOA serial would change on every run), so those are left
+// out, with a warning if 'warn' is set.
+fn unmanaged(sets: &HashMap<String, Route53Record>, warn: bool) -> HashMap<String, Route53Record> {
+    let apexes = provider::zone_apexes(sets);
+    let mut retval = HashMap::new();
+    for (key, set) in sets {
+        if provider::service_managed(set, &apexes) {
+            if warn {
+                println!("Warning: skipping {} record for {}, Route53 manages it",
+                         set.rtype, set.name);
+            }
+            continue;
+        }
+        retval.insert(key.to_string(), set.clone());
+    }
+    retval
+}
+
+// Build an UPSERT for every record set, grouped by hosted zone.  Within a
+// zone the changes are sorted by name and type so the output is stable.
+pub fn upserts(sets: &HashMap<String, Route53Record>) -> BTreeMap<String, Vec<Change>> {
+    let sets = unmanaged(sets, true);
+    let mut retval: BTreeMap<String, Vec<Change>> = BTreeMap::new();
+    let mut sorted: Vec<&Route53Record> = sets.values().collect();
+    sorted.sort_by(|a, b| (&a.name, &a.rtype).cmp(&(&b.name, &b.rtype)));
+    for rec in sorted {
+        retval.entry(rec.zone_id.to_string()).or_default()
+            .push(Change::new("UPSERT", to_resource_record_set(rec)));
+    }
+    retval
+}
+
+// Work out the changes that take a zone from the old record sets to the new
+// ones: CREATE for new sets, UPSERT for changed ones and DELETE for those
+// that are gone, grouped by hosted zone.  Route53 only deletes a set that
+// matches exactly, so DELETEs are built from the old set as it stands.
+// They come first so a name can switch record type (e.g. A to CNAME)
+// without the two clashing.  Sets Route53 manages are left out of both.
+pub fn changes(old: &HashMap<String, Route53Record>, new: &HashMap<String, Route53Record>)
+               -> BTreeMap<String, Vec<Change>> {
+    let new = unmanaged(new, true);
+    let d = diff::diff(&unmanaged(old, false), &new);
+    let mut retval: BTreeMap<String, Vec<Change>> = BTreeMap::new();
+
+    for o in &d.removed {
+        retval.entry(o.zone_id.to_string()).or_default()
+            .push(Change::new("DELETE", to_resource_record_set(o)));
+    }
+    for n in &d.added {
+        retval.entry(n.zone_id.to_string()).or_default()
+            .push(Change::new("CREATE", to_resource_record_set(n)));
+    }
+    for c in &d.modified {
+        let n = &new[&provider::resource_name(&c.rtype, &c.name)];
+        retval.entry(n.zone_id.to_string()).or_default()
+            .push(Change::new("UPSERT", to_resource_record_set(n)));
+    }
+    retval
+}
+
+// Pack changes, in order, into as few ChangeBatches as Route53's request
+// limits allow.  A change too big to fit in a request on its own is an
+// error.
+pub fn to_change_batches(changes: Vec<Change>) -> Result<Vec<ChangeBatch>, String> {
+    let mut retval = Vec::new();
+    let mut current: Vec<Change> = Vec::new();
+    let (mut records, mut chars) = (0, 0);
+
+    for change in changes {
+        let (r, c) = change.weight();
+        if r > MAX_BATCH_RECORDS || c > MAX_BATCH_CHARS {
+            return Err(format!("{} {} {} is too large for a single Route53 request",
+                               change.action, change.resource_record_set.rtype,
+                               change.resource_record_set.name));
+        }
+        if records + r > MAX_BATCH_RECORDS || chars + c > MAX_BATCH_CHARS {
+            retval.push(ChangeBatch { comment: None, changes: current });
+            current = Vec::new();
+            records = 0;
+            chars = 0;
+        }
+        records += r;
+        chars += c;
+        current.push(change);
+    }
+    if !current.is_empty() {
+        retval.push(ChangeBatch { comment: None, changes: current });
+    }
+
+    // Number the batches so they can be told apart when applied
+    let count = retval.len();
+    for (i, batch) in retval.iter_mut().enumerate() {
+        batch.comment = Some(format!("tiny2terra change batch {} of {}", i + 1, count));
+    }
+    Ok(retval)
+}
+
+// Given a Vec of ResourceRecordSets, build a HashMap of Route53Records keyed
+// the same way route53::generate keys them, so the two can be compared.
+// Names lose their trailing dot and any \NNN escapes, TXT values are
+// unquoted, and the zone ID is left empty.  Sets tinydns can't express are
+// skipped and returned separately as descriptions.
+pub fn to_route53_records(sets: &[ResourceRecordSet])
+                          -> (HashMap<String, Route53Record>, Vec<String>) {
+    let mut retval = HashMap::new();
+    let mut skipped = Vec::new();
+
+    for set in sets {
+        let name = tinydns::unescape(set.name.trim_end_matches('.'));
+        if let Some(why) = set.unsupported() {
+            skipped.push(format!("{} {}: {}", set.rtype, name, why));
+            continue;
+        }
+        let records = set.resource_records.iter().map(|r| {
+            match set.rtype.as_str() {
+                "TXT" => unquote_txt(&r.value),
+                "CAA" => {
+                    let fields: Vec<&str> = r.value.splitn(3, ' ').collect();
+                    match fields.len() {
+                        3 => format!("{} {} {}", fields[0], fields[1], unquote_txt(fields[2])),
+                        _ => r.value.to_string()
+                    }
+                },
+                _     => r.value.to_string()
+            }
+        }).collect();
+        let r53r = Route53Record {
+            zone_id: String::new(),
+            name,
+            rtype:   set.rtype.to_string(),
+            records,
+            // Route53 holds TTLs to the same range we do
+            ttl:     set.ttl
+                .unwrap_or(i64::from(tinydns::DEFAULT_TTL))
+                .clamp(0, i64::from(tinydns::MAX_TTL)) as u32
+        };
+        retval.insert(provider::resource_name(&r53r.rtype, &r53r.name), r53r);
+    }
+
+    (retval, skipped)
+}
+
+// Turn record sets from the API into TinyDNSRecords, along with '#'
+// comments describing any sets tinydns can't express
+pub fn to_tinydns(sets: &[ResourceRecordSet]) -> (Vec<TinyDNSRecord>, Vec<String>) {
+    let (r53_records, skipped) = to_route53_records(sets);
+    let comments = skipped.iter().map(|x| format!("# Skipped {}", x)).collect();
+    (route53::to_tinydns(&r53_records), comments)
+}
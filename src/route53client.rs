@@ -0,0 +1,440 @@
+// Define a small client for the parts of the Route53 API tiny2terra talks
+// to directly, signing requests with AWS Signature Version 4.  The
+// endpoint is configurable so it can be pointed at a mock server.
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use hex;
+use ureq;
+use roxmltree;
+use serde_json;
+use route53api::{AliasTarget, ChangeBatch, ResourceRecord, ResourceRecordSet};
+use tinydns;
+
+pub const DEFAULT_ENDPOINT: &str = "https://route53.amazonaws.com";
+const API_VERSION: &str = "2013-04-01";
+// Route53 is a global service, but requests are signed for us-east-1
+const REGION: &str = "us-east-1";
+const SERVICE: &str = "route53";
+
+// AWS credentials, as found in the usual environment variables
+pub struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub session_token: Option<String>
+}
+
+impl Credentials {
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| ::std::env::var(name).ok().filter(|x| !x.is_empty());
+        Some(Credentials {
+            access_key:    var("AWS_ACCESS_KEY_ID")?,
+            secret_key:    var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN")
+        })
+    }
+}
+
+// Query string parameters as (name, value) pairs, unencoded
+pub type Query = Vec<(String, String)>;
+
+// An API request before signing.  Dry runs print these instead of sending
+// them.
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Query,
+    pub body: String
+}
+
+impl Request {
+    // The query string, encoded and sorted the way SigV4 wants it
+    pub fn query_string(&self) -> String {
+        let mut pairs: Vec<String> = self.query.iter()
+            .map(|(k, v)| format!("{}={}", uri_encode(k), uri_encode(v)))
+            .collect();
+        pairs.sort();
+        pairs.join("&")
+    }
+
+    pub fn to_text(&self, endpoint: &str) -> String {
+        let mut url = format!("{}{}", endpoint.trim_end_matches('/'), self.path);
+        if !self.query.is_empty() {
+            url = format!("{}?{}", url, self.query_string());
+        }
+        match self.body.is_empty() {
+            true => format!("{} {}", self.method, url),
+            false => format!("{} {}\n{}", self.method, url, self.body)
+        }
+    }
+}
+
+// Where a submitted change is up to
+#[derive(Debug, PartialEq)]
+pub struct ChangeInfo {
+    pub id: String,
+    pub status: String,
+    pub submitted_at: String
+}
+
+pub struct Client {
+    pub endpoint: String,
+    credentials: Credentials,
+    agent: ureq::Agent
+}
+
+impl Client {
+    pub fn new(endpoint: &str, credentials: Credentials) -> Self {
+        Client {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            credentials,
+            agent: ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build()
+        }
+    }
+
+    // Sign and send a request, handing back the response body.  API errors
+    // come back as their message.
+    pub fn send(&self, req: &Request) -> Result<String, String> {
+        let host = host(&self.endpoint);
+        let headers = sign(req, &host, &self.credentials, &amz_date(now()), REGION, SERVICE);
+        let mut url = format!("{}{}", self.endpoint, req.path);
+        if !req.query.is_empty() {
+            url = format!("{}?{}", url, req.query_string());
+        }
+
+        let mut call = self.agent.request(&req.method, &url);
+        for (name, value) in &headers {
+            if name != "host" {
+                call = call.set(name, value);
+            }
+        }
+        let result = match req.body.is_empty() {
+            true => call.call(),
+            false => call.set("content-type", "application/xml").send_string(&req.body)
+        };
+
+        match result {
+            Ok(resp) => resp.into_string().map_err(|e| e.to_string()),
+            Err(ureq::Error::Status(code, resp)) => {
+                let body = resp.into_string().unwrap_or_default();
+                Err(format!("HTTP {}: {}", code, error_message(&body)))
+            },
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    pub fn change_resource_record_sets(&self, zone: &str, batch: &ChangeBatch)
+                                       -> Result<ChangeInfo, String> {
+        parse_change_info(&self.send(&change_request(zone, batch))?)
+    }
+
+    pub fn get_change(&self, id: &str) -> Result<ChangeInfo, String> {
+        parse_change_info(&self.send(&get_change_request(id))?)
+    }
+
+    // List every record set in a hosted zone, following the pagination
+    pub fn list_resource_record_sets(&self, zone: &str) -> Result<Vec<ResourceRecordSet>, String> {
+        let mut retval = Vec::new();
+        let mut next = Vec::new();
+        loop {
+            let (mut sets, more) = parse_record_sets(&self.send(&list_request(zone, next))?)?;
+            retval.append(&mut sets);
+            match more {
+                Some(x) => next = x,
+                None => return Ok(retval)
+            }
+        }
+    }
+
+    // Poll GetChange until the change is INSYNC or we run out of time
+    pub fn wait_for_change(&self, id: &str, interval: Duration, timeout: Duration)
+                           -> Result<ChangeInfo, String> {
+        let start = Instant::now();
+        loop {
+            let info = self.get_change(id)?;
+            if info.status == "INSYNC" {
+                return Ok(info);
+            }
+            if start.elapsed() + interval > timeout {
+                return Err(format!("Change {} still {} after {} seconds",
+                                   id, info.status, timeout.as_secs()));
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+// Zone and change IDs can be given with or without their path prefix
+fn bare_id(id: &str) -> &str {
+    id.trim_start_matches("/hostedzone/").trim_start_matches("/change/")
+}
+
+// Build a ChangeResourceRecordSets request for a hosted zone
+pub fn change_request(zone: &str, batch: &ChangeBatch) -> Request {
+    Request {
+        method: "POST".to_string(),
+        path:   format!("/{}/hostedzone/{}/rrset", API_VERSION, bare_id(zone)),
+        query:  Vec::new(),
+        body:   change_xml(batch)
+    }
+}
+
+pub fn get_change_request(id: &str) -> Request {
+    Request {
+        method: "GET".to_string(),
+        path:   format!("/{}/change/{}", API_VERSION, bare_id(id)),
+        query:  Vec::new(),
+        body:   String::new()
+    }
+}
+
+// Build a ListResourceRecordSets request, starting from where the previous
+// page said to carry on (if anywhere)
+pub fn list_request(zone: &str, start: Query) -> Request {
+    Request {
+        method: "GET".to_string(),
+        path:   format!("/{}/hostedzone/{}/rrset", API_VERSION, bare_id(zone)),
+        query:  start,
+        body:   String::new()
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+        .replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+// Render a ResourceRecordSet as the API's XML
+fn record_set_xml(set: &ResourceRecordSet) -> String {
+    let mut xml = format!("<ResourceRecordSet><Name>{}</Name><Type>{}</Type>",
+                          xml_escape(&set.name), xml_escape(&set.rtype));
+    if let Some(ttl) = set.ttl {
+        xml.push_str(&format!("<TTL>{}</TTL>", ttl));
+    }
+    if let Some(ref alias) = set.alias_target {
+        xml.push_str(&format!("<AliasTarget><HostedZoneId>{}</HostedZoneId>\
+                               <DNSName>{}</DNSName>\
+                               <EvaluateTargetHealth>{}</EvaluateTargetHealth></AliasTarget>",
+                              xml_escape(&alias.hosted_zone_id), xml_escape(&alias.dns_name),
+                              alias.evaluate_target_health));
+    }
+    if !set.resource_records.is_empty() {
+        xml.push_str("<ResourceRecords>");
+        for r in &set.resource_records {
+            xml.push_str(&format!("<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                                  xml_escape(&r.value)));
+        }
+        xml.push_str("</ResourceRecords>");
+    }
+    xml.push_str("</ResourceRecordSet>");
+    xml
+}
+
+// Render a ChangeBatch as a ChangeResourceRecordSetsRequest body
+pub fn change_xml(batch: &ChangeBatch) -> String {
+    let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                           <ChangeResourceRecordSetsRequest \
+                           xmlns=\"https://route53.amazonaws.com/doc/{}/\"><ChangeBatch>",
+                          API_VERSION);
+    if let Some(ref comment) = batch.comment {
+        xml.push_str(&format!("<Comment>{}</Comment>", xml_escape(comment)));
+    }
+    xml.push_str("<Changes>");
+    for change in &batch.changes {
+        xml.push_str(&format!("<Change><Action>{}</Action>{}</Change>",
+                              xml_escape(&change.action),
+                              record_set_xml(&change.resource_record_set)));
+    }
+    xml.push_str("</Changes></ChangeBatch></ChangeResourceRecordSetsRequest>");
+    xml
+}
+
+// Text of the first element with the given name, ignoring namespaces
+pub fn child_text<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+    node.descendants().find(|n| n.tag_name().name() == name).and_then(|n| n.text())
+}
+
+// First direct child element with the given name, ignoring namespaces
+fn child<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<roxmltree::Node<'a, 'a>> {
+    node.children().find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn child_string(node: roxmltree::Node, name: &str) -> Option<String> {
+    child(node, name).map(|n| n.text().unwrap_or("").to_string())
+}
+
+// Read a ResourceRecordSet element into the same struct the JSON export is
+// read into
+fn parse_record_set(node: roxmltree::Node) -> Result<ResourceRecordSet, String> {
+    let number = |name: &str| -> Result<Option<i64>, String> {
+        match child_string(node, name) {
+            Some(x) => x.parse::<i64>().map(Some).map_err(|_| format!("Bad {} '{}'", name, x)),
+            None => Ok(None)
+        }
+    };
+    let resource_records = match child(node, "ResourceRecords") {
+        Some(rrs) => rrs.children().filter(|n| n.is_element())
+            .map(|r| ResourceRecord { value: child_string(r, "Value").unwrap_or_default() })
+            .collect(),
+        None => Vec::new()
+    };
+    let alias_target = child(node, "AliasTarget").map(|a| AliasTarget {
+        hosted_zone_id:         child_string(a, "HostedZoneId").unwrap_or_default(),
+        dns_name:               child_string(a, "DNSName").unwrap_or_default(),
+        evaluate_target_health: child_string(a, "EvaluateTargetHealth").as_deref() == Some("true")
+    });
+    // Only kept to be reported, so the fields are carried as they are
+    let geo_location = child(node, "GeoLocation").map(|g| {
+        let fields = g.children().filter(|n| n.is_element())
+            .map(|n| (n.tag_name().name().to_string(),
+                      serde_json::Value::String(n.text().unwrap_or("").to_string())))
+            .collect();
+        serde_json::Value::Object(fields)
+    });
+
+    Ok(ResourceRecordSet {
+        name:               child_string(node, "Name").ok_or("Record set with no Name")?,
+        rtype:              child_string(node, "Type").ok_or("Record set with no Type")?,
+        ttl:                number("TTL")?,
+        resource_records,
+        alias_target,
+        set_identifier:     child_string(node, "SetIdentifier"),
+        weight:             number("Weight")?,
+        region:             child_string(node, "Region"),
+        failover:           child_string(node, "Failover"),
+        geo_location,
+        multi_value_answer: child_string(node, "MultiValueAnswer").map(|x| x == "true"),
+        health_check_id:    child_string(node, "HealthCheckId")
+    })
+}
+
+// Read one page of a ListResourceRecordSets response.  If there are more
+// pages, also hand back the query that fetches the next one.
+pub fn parse_record_sets(xml: &str) -> Result<(Vec<ResourceRecordSet>, Option<Query>), String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+    let root = doc.root_element();
+
+    let mut sets = Vec::new();
+    if let Some(rrsets) = child(root, "ResourceRecordSets") {
+        for node in rrsets.children().filter(|n| n.is_element()) {
+            sets.push(parse_record_set(node)?);
+        }
+    }
+
+    if child_string(root, "IsTruncated").as_deref() != Some("true") {
+        return Ok((sets, None));
+    }
+    let mut next = Vec::new();
+    for (param, field) in &[("name", "NextRecordName"), ("type", "NextRecordType"),
+                            ("identifier", "NextRecordIdentifier")] {
+        if let Some(x) = child_string(root, field) {
+            next.push((param.to_string(), x));
+        }
+    }
+    if next.is_empty() {
+        return Err("Truncated response with no NextRecordName".to_string());
+    }
+    Ok((sets, Some(next)))
+}
+
+// Pull the ChangeInfo out of a ChangeResourceRecordSets or GetChange
+// response
+pub fn parse_change_info(xml: &str) -> Result<ChangeInfo, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| e.to_string())?;
+    let root = doc.root_element();
+    let field = |name: &str| {
+        child_text(root, name).map(|x| x.to_string())
+            .ok_or_else(|| format!("No {} in response", name))
+    };
+    Ok(ChangeInfo {
+        id:           bare_id(&field("Id")?).to_string(),
+        status:       field("Status")?,
+        submitted_at: field("SubmittedAt").unwrap_or_default()
+    })
+}
+
+// The message from an API error response, or the whole body if it isn't
+// one
+fn error_message(body: &str) -> String {
+    match roxmltree::Document::parse(body) {
+        Ok(doc) => child_text(doc.root_element(), "Message").unwrap_or(body).to_string(),
+        Err(_) => body.to_string()
+    }
+}
+
+// The host (and port, if any) part of an endpoint URL
+fn host(endpoint: &str) -> String {
+    let rest = endpoint.split_once("://").map_or(endpoint, |x| x.1);
+    rest.split('/').next().unwrap_or("").to_string()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Format seconds since the epoch as an ISO 8601 basic timestamp, e.g.
+// 20150830T123600Z
+pub fn amz_date(secs: u64) -> String {
+    let (year, month, day) = tinydns::civil_date(secs);
+    let rem = secs % 86400;
+    format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day,
+            rem / 3600, (rem % 3600) / 60, rem % 60)
+}
+
+// Percent-encode everything but the unreserved characters, as SigV4 wants
+pub fn uri_encode(text: &str) -> String {
+    let mut out = String::new();
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            },
+            _ => out.push_str(&format!("%{:02X}", b))
+        }
+    }
+    out
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Work out the SigV4 headers for a request: host, x-amz-date, the session
+// token if there is one, and the Authorization header itself
+pub fn sign(req: &Request, host: &str, creds: &Credentials, amz_date: &str,
+            region: &str, service: &str) -> Vec<(String, String)> {
+    let date = &amz_date[..8];
+    let mut headers = vec![("host".to_string(), host.to_string()),
+                           ("x-amz-date".to_string(), amz_date.to_string())];
+    if let Some(ref token) = creds.session_token {
+        headers.push(("x-amz-security-token".to_string(), token.to_string()));
+    }
+
+    let canonical_headers: String = headers.iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+    let signed_headers = headers.iter().map(|(k, _)| k.as_str())
+        .collect::<Vec<&str>>().join(";");
+    let canonical_uri = req.path.split('/').map(uri_encode).collect::<Vec<String>>().join("/");
+    let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", req.method, canonical_uri,
+                                    req.query_string(), canonical_headers, signed_headers,
+                                    hex::encode(Sha256::digest(req.body.as_bytes())));
+
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope,
+                                 hex::encode(Sha256::digest(canonical_request.as_bytes())));
+
+    let k_date = hmac_sha256(format!("AWS4{}", creds.secret_key).as_bytes(), date);
+    let k_region = hmac_sha256(&k_date, region);
+    let k_service = hmac_sha256(&k_region, service);
+    let k_signing = hmac_sha256(&k_service, "aws4_request");
+    let signature = hex::encode(hmac_sha256(&k_signing, &string_to_sign));
+
+    headers.push(("authorization".to_string(),
+                  format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                          creds.access_key, scope, signed_headers, signature)));
+    headers
+}
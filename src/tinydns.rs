@@ -1,12 +1,120 @@
 // Define functions for processing TinyDNS flat files
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, BufRead};
 use std::time::SystemTime;
 use std::net::Ipv4Addr;
+use std::fmt::Display;
+use std::str::FromStr;
 use types::TinyDNSRecord;
 
+// TTL for records that don't give one, unless told otherwise
+pub const DEFAULT_TTL: u32 = 300;
+
+// Longest TTL allowed (RFC 2181 section 8), which also bounds the SOA
+// refresh, retry, expire and minimum timers
+pub const MAX_TTL: u32 = 2147483647;
+
+// Where SOA serials come from when a 'Z' line leaves them out, or for '.'
+// lines, which can't give one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialSource {
+    // Seconds since the epoch, from the clock
+    Now,
+    // Seconds since the epoch when the input file was last modified, as
+    // tinydns-data does.  Falls back to the clock without a file.
+    Mtime,
+    // The same serial every time
+    Fixed(u32),
+    // The SOURCE_DATE_EPOCH environment variable, for reproducible builds
+    SourceDateEpoch,
+    // YYYYMMDDnn from the clock's (UTC) date and a counter for changes
+    // within the day, which has to be bumped by hand
+    Date(u8)
+}
+
+impl SerialSource {
+    // Parse a --serial value: now, mtime, source-date-epoch, date, date:NN
+    // or a number
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "now" => Ok(SerialSource::Now),
+            "mtime" => Ok(SerialSource::Mtime),
+            "source-date-epoch" => Ok(SerialSource::SourceDateEpoch),
+            "date" => Ok(SerialSource::Date(0)),
+            x if x.starts_with("date:") => match x[5..].parse::<u8>() {
+                Ok(n) if n < 100 => Ok(SerialSource::Date(n)),
+                _ => Err(format!("Bad serial source '{}', the date counter goes from 0 to 99", x))
+            },
+            x => x.parse::<u32>().map(SerialSource::Fixed).map_err(|_| {
+                format!("Bad serial source '{}', expected now, mtime, source-date-epoch, \
+                         date, date:NN or a number", x)
+            })
+        }
+    }
+}
+
+// How forgiving to be about the numeric fields of a data file, and what
+// to use for fields left out
+#[derive(Debug, Clone, Copy)]
+pub struct ParseOptions {
+    // Reject malformed or out-of-range TTLs, MX distances and SOA numbers
+    // rather than falling back to the field's default
+    pub strict: bool,
+    pub default_ttl: u32,
+    pub serial: SerialSource,
+    // Seconds since the epoch, swappable so tests can fix the time
+    pub clock: fn() -> u64,
+    // Environment variable lookup, swappable so tests needn't touch the
+    // process environment
+    pub env: fn(&str) -> Option<String>
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            strict:      false,
+            default_ttl: DEFAULT_TTL,
+            serial:      SerialSource::Now,
+            clock:       epoch_now,
+            env:         env_var
+        }
+    }
+}
+
+impl ParseOptions {
+    // Work out the serial for SOAs that don't give one, given the input
+    // file's modification time if there is a file.  Serials are 32 bits,
+    // which seconds since the epoch fit into until 2106; anything bigger
+    // is an error rather than being wrapped.
+    pub fn default_serial(&self, mtime: Option<u64>) -> Result<u32, String> {
+        match self.serial {
+            SerialSource::Now => serial_from((self.clock)(), "The time"),
+            SerialSource::Mtime => serial_from(mtime.unwrap_or_else(self.clock), "The file time"),
+            SerialSource::Fixed(x) => Ok(x),
+            SerialSource::SourceDateEpoch => match (self.env)("SOURCE_DATE_EPOCH") {
+                Some(x) => match x.trim().parse::<u64>() {
+                    Ok(n) => serial_from(n, "SOURCE_DATE_EPOCH"),
+                    Err(_) => Err(format!("Bad SOURCE_DATE_EPOCH '{}'", x))
+                },
+                None => Err("SOURCE_DATE_EPOCH isn't set".to_string())
+            },
+            SerialSource::Date(n) => {
+                let (year, month, day) = civil_date((self.clock)());
+                let date = year * 1000000 + i64::from(month * 10000 + day * 100 + u32::from(n));
+                serial_from(date as u64, "The date serial")
+            }
+        }
+    }
+}
+
 // Given a filename, read in the contents and generate a Vec of TDRs
 pub fn from_file(fname: &str) -> Option<Vec<TinyDNSRecord>> {
+    from_file_with(fname, &ParseOptions::default())
+}
+
+// As from_file, with control over how numeric fields are parsed
+pub fn from_file_with(fname: &str, opts: &ParseOptions) -> Option<Vec<TinyDNSRecord>> {
     let mut retval = Vec::new();
     let mut error_flag = false;
 
@@ -18,34 +126,36 @@ pub fn from_file(fname: &str) -> Option<Vec<TinyDNSRecord>> {
             return None;
         }
     };
+
+    // Settle the default serial once, so every SOA in the file agrees
+    let mtime = f.metadata().and_then(|m| m.modified()).ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    let opts = match opts.default_serial(mtime) {
+        Ok(x) => ParseOptions { serial: SerialSource::Fixed(x), ..*opts },
+        Err(e) => {
+            println!("Error working out SOA serials for {}: {}", fname, e);
+            return None;
+        }
+    };
     let reader = BufReader::new(&f);
 
-    // Process each line in the file and call the appropriate parsing
-    // function.  Remember that some prefixes generate more than one!
-    // Because of that, all the parse_X functions return a vector that
-    // can be simply append()-ed to retval. If there's an error, we simply
-    // get back an empty vector.
-    for line in reader.lines() {
+    // Process each line in the file.  Remember that some prefixes generate
+    // more than one record!  If there's an error, we simply get back an
+    // empty vector; comments and blank lines are skipped before that check.
+    for (n, line) in reader.lines().enumerate() {
         let l = line.expect("Couldn't get line?");
-        let (prefix, data) = l.split_at(1);
-        let mut parsed = match prefix {
-            "+" => { parse("A", data) },
-            "^" => { parse("PTR", data) },
-            "C" => { parse("CNAME", data) },
-            "'" => { parse("TXT", data) },
-            "@" => { parse_mx(data) },
-            "Z" => { parse_soa(data) },
-            "." => { parse_anssoa(data) },
-            "&" => { parse_ans(data) },
-            "=" => { parse_aptr(data) },
-            _   => {
-                println!("Unsuported prefix: {}", prefix);
-                Vec::new()
-            }
-        };
+        if is_ignored(&l) {
+            continue;
+        }
+        let mut parsed = parse_line_with(&l, &opts);
         if parsed.is_empty() {
             error_flag = true;
         } else {
+            // Remember where each record came from for error reporting
+            for tdr in &mut parsed {
+                tdr.line = n + 1;
+            }
             retval.append(&mut parsed);
         }
     }
@@ -57,12 +167,84 @@ pub fn from_file(fname: &str) -> Option<Vec<TinyDNSRecord>> {
     }
 }
 
+// tinydns-data skips blank lines, '#' comments and '-' (disabled) lines
+pub fn is_ignored(line: &str) -> bool {
+    let l = line.trim();
+    l.is_empty() || l.starts_with('#') || l.starts_with('-')
+}
+
+// Given a single line of a data file, call the appropriate parsing function.
+// All the parse_X functions return a vector that can be simply append()-ed
+// onto a larger one.  Errors come back as an empty vector.
+pub fn parse_line(line: &str) -> Vec<TinyDNSRecord> {
+    parse_line_with(line, &ParseOptions::default())
+}
+
+// As parse_line, with control over how numeric fields are parsed
+pub fn parse_line_with(line: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
+    let l = line.trim_end();
+    if l.is_empty() {
+        println!("Error parsing empty line");
+        return Vec::new();
+    }
+    let split = l.char_indices().nth(1).map(|(i, _)| i).unwrap_or(l.len());
+    let (prefix, data) = l.split_at(split);
+    match prefix {
+        "+" => { parse("A", data, opts) },
+        "^" => { parse("PTR", data, opts) },
+        "C" => { parse("CNAME", data, opts) },
+        "'" => { parse("TXT", data, opts) },
+        "@" => { parse_mx(data, opts) },
+        "Z" => { parse_soa(data, opts) },
+        "." => { parse_anssoa(data, opts) },
+        "&" => { parse_ans(data, opts) },
+        "=" => { parse_aptr(data, opts) },
+        _   => {
+            println!("Unsuported prefix: {}", prefix);
+            Vec::new()
+        }
+    }
+}
+
+// Parse a numeric field no bigger than 'max', where an empty field gets
+// 'default'.  Anything else that isn't a number in range is an error in
+// strict mode, and falls back to the default with a warning otherwise.
+fn number<T>(field: &str, what: &str, default: T, max: T, opts: &ParseOptions)
+             -> Result<T, String>
+    where T: FromStr + PartialOrd + Display + Copy {
+    if field.is_empty() {
+        return Ok(default);
+    }
+    match field.parse::<T>() {
+        Ok(x) if x <= max => Ok(x),
+        _ => match opts.strict {
+            true => Err(format!("Bad {} '{}', expected a number from 0 to {}", what, field, max)),
+            false => {
+                println!("Warning: bad {} '{}', using {}", what, field, default);
+                Ok(default)
+            }
+        }
+    }
+}
+
+// The i'th optional field left on a line, or "" if there aren't that many
+fn field<'a>(parts: &[&'a str], i: usize) -> &'a str {
+    parts.get(i).cloned().unwrap_or("")
+}
+
+// Report a record whose fields don't make sense; the caller then returns
+// no records for it
+fn bad_record(data: &str, e: &str) {
+    println!("Error processing record: {}", data);
+    println!("{}", e);
+}
+
 // Parse a basic DNS record into 1 TinyDNSRecord
 // +fqdn:rec:ttl:timestamp:lo - A
 // ^fqdn:rec:ttl:timestamp:lo - PTR
 // Cfqdn:rec:ttl:timestamp:lo - CNAME
 // 'fqdn:rec:ttl:timestamp:lo - TXT
-pub fn parse(rtype: &str, data: &str) -> Vec<TinyDNSRecord> {
+pub fn parse(rtype: &str, data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create our return Vec
     let mut retval = Vec::new();
 
@@ -81,8 +263,12 @@ pub fn parse(rtype: &str, data: &str) -> Vec<TinyDNSRecord> {
     let rec = parts.remove(0);
 
     // Just in case there are extraneous quotes - Terraform gets angry
-    // about those.
-    let target = rec.to_string().replace("\"", "");
+    // about those.  TXT data may also carry octal escapes for colons and
+    // other unprintables, so decode those too.
+    let target = match rtype {
+        "TXT" => unescape(rec).replace("\"", ""),
+        _     => rec.to_string().replace("\"", "")
+    };
 
     // If this is an 'A' record, we should ensure 'rec' is a valid IPv4 addr
     if rtype == "A" {
@@ -97,12 +283,12 @@ pub fn parse(rtype: &str, data: &str) -> Vec<TinyDNSRecord> {
     }
 
     // See if there's a TTL in there since it would come next
-    // Assign a default value of 300 if there's none provided
-    // or if it can't be parsed as an i32.
-    let ttl = match parts.is_empty() {
-        true => 300,
-        false => {
-            parts.remove(0).parse::<i32>().unwrap_or(300)
+    // Assign the default if there's none provided
+    let ttl = match number(field(&parts, 0), "TTL", opts.default_ttl, MAX_TTL, opts) {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
         }
     };
 
@@ -111,8 +297,10 @@ pub fn parse(rtype: &str, data: &str) -> Vec<TinyDNSRecord> {
     let tdr = TinyDNSRecord {
         rtype: rtype.to_string(),
         fqdn:  fqdn.to_string(),
-        target: target,
-        ttl: ttl
+        target,
+        ttl,
+        line:  0,
+        source: None
     };
     retval.push(tdr);
 
@@ -122,8 +310,8 @@ pub fn parse(rtype: &str, data: &str) -> Vec<TinyDNSRecord> {
 // Parse an MX record into two TinyDNSRecords
 // @fqdn:ip:x:dist:ttl:timestamp:lo
 // (1) type=MX, fqdn=fqdn, target="dist x(.mx.fqdn)"
-// (2) type=A,  fqdn=x(.mx.fqdn), target=ip
-pub fn parse_mx(data: &str) -> Vec<TinyDNSRecord> {
+// (2) type=A,  fqdn=x(.mx.fqdn), target=ip (skipped if ip is empty)
+pub fn parse_mx(data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create return vec
     let mut retval = Vec::new();
 
@@ -141,13 +329,15 @@ pub fn parse_mx(data: &str) -> Vec<TinyDNSRecord> {
     let ip = parts.remove(0);
     let x = parts.remove(0);
 
-    // Make sure IP is an IP
-    match ip.parse::<Ipv4Addr>() {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Error processing record: {}", data);
-            println!("{}", e);
-            return retval;
+    // Make sure IP is an IP, if one was given at all
+    if !ip.is_empty() {
+        match ip.parse::<Ipv4Addr>() {
+            Ok(_) => {},
+            Err(e) => {
+                println!("Error processing record: {}", data);
+                println!("{}", e);
+                return retval;
+            }
         }
     }
 
@@ -158,14 +348,17 @@ pub fn parse_mx(data: &str) -> Vec<TinyDNSRecord> {
         false => format!("{}.mx.{}", x, fqdn)
     };
 
-    // Do some fancy matching footwork to populate the mx_dist and ttl
-    // depending on whether they were provided. Even though mx_dist will
-    // wind up as part of a string, make sure it's a valid integer first.
-    let (mx_dist, ttl) = match parts.len() {
-        0 => (0, 300),
-        1 => (parts.remove(0).parse::<i32>().unwrap_or(0), 300),
-        _ => (parts.remove(0).parse::<i32>().unwrap_or(0),
-              parts.remove(0).parse::<i32>().unwrap_or(300))
+    // Populate the mx_dist and ttl, defaulting whichever weren't
+    // provided.  Even though mx_dist will wind up as part of a string,
+    // make sure it's a valid 16 bit integer first.
+    let numbers = number(field(&parts, 0), "MX distance", 0, u16::MAX, opts)
+        .and_then(|d| Ok((d, number(field(&parts, 1), "TTL", opts.default_ttl, MAX_TTL, opts)?)));
+    let (mx_dist, ttl) = match numbers {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
+        }
     };
 
     // Generate MX TDR
@@ -173,28 +366,72 @@ pub fn parse_mx(data: &str) -> Vec<TinyDNSRecord> {
         rtype:   "MX".to_string(),
         fqdn:    fqdn.to_string(),
         target:  format!("{} {}", mx_dist, mx_fqdn),
-        ttl:     ttl
+        ttl,
+        line:    0,
+        source:  None
     };
     retval.push(tdr1);
 
-    // Generate A TDR
-    let tdr2 = TinyDNSRecord {
-        rtype:  "A".to_string(),
-        fqdn:   mx_fqdn,
-        target: ip.to_string(),
-        ttl:    ttl
-    };
-    retval.push(tdr2);
+    // Generate A TDR, unless the IP was left empty
+    if !ip.is_empty() {
+        let tdr2 = TinyDNSRecord {
+            rtype:  "A".to_string(),
+            fqdn:   mx_fqdn,
+            target: ip.to_string(),
+            ttl,
+            line:   0,
+            source: None
+        };
+        retval.push(tdr2);
+    }
 
     // Return Vec
     retval
 }
 
+// Seconds since the epoch, the clock SOA serials normally come from
+pub fn epoch_now() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => panic!("Something is REALLY wrong, SystemTime < EPOCH??")
+    }
+}
+
+// A number as a 32-bit SOA serial, or an error saying what it was if it
+// doesn't fit
+fn serial_from(n: u64, what: &str) -> Result<u32, String> {
+    match n > u64::from(u32::MAX) {
+        true => Err(format!("{} {} is too big for a 32-bit SOA serial", what, n)),
+        false => Ok(n as u32)
+    }
+}
+
+// An environment variable from the process environment, if it's set (and
+// is Unicode)
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+// The (UTC) year, month and day of a time in seconds since the epoch,
+// using Howard Hinnant's civil-from-days algorithm
+pub fn civil_date(secs: u64) -> (i64, u32, u32) {
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
 // Parse an SOA record 
 // Zfqdn:ns:contact:serial:refresh:retry:expire:min:ttl:timestamp:lo
 // serial, refresh, retry, expire, and min are optional and default to
-// epoch, 16384, 2048, 1048576, and 2560.
-pub fn parse_soa(data: &str) -> Vec<TinyDNSRecord> {
+// the options' default serial, 16384, 2048, 1048576, and 2560.
+pub fn parse_soa(data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create return vec
     let mut retval = Vec::new();
 
@@ -212,45 +449,32 @@ pub fn parse_soa(data: &str) -> Vec<TinyDNSRecord> {
     let ns      = parts.remove(0);
     let contact = parts.remove(0);
 
-    // As with MX, we can do some fancy footwork with match based on how
-    // many items are left in the parts vector.  Start by getting an
-    // epoch time in case we need it.
-    let right_now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(n) => n.as_secs(),
-        Err(_) => panic!("Something is REALLY wrong, SystemTime < EPOCH??")
+    // Each of the numbers is optional and gets its default if it's left
+    // out.  The default serial is only worked out if it's needed, since
+    // that can fail.
+    let serial = || match field(&parts, 0).parse::<u32>() {
+        Ok(x) => Ok(x),
+        Err(_) => opts.default_serial(None)
+            .and_then(|d| number(field(&parts, 0), "SOA serial", d, u32::MAX, opts))
     };
-
-    // Now the match game. Again these wind up in a string but we want to
-    // ensure they are valid integers first.
-    let (ser, refr, retr, exp, min, ttl) = match parts.len() {
-        0 => (right_now, 16384, 2048, 1048576, 2560, 300),
-        1 => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              16384, 2048, 1048576, 2560, 300),
-        2 => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              parts.remove(0).parse::<i32>().unwrap_or(16384),
-              2048, 1048576, 2560, 300),
-        3 => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              parts.remove(0).parse::<i32>().unwrap_or(16384),
-              parts.remove(0).parse::<i32>().unwrap_or(2048),
-              1048576, 2560, 300),
-        4 => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              parts.remove(0).parse::<i32>().unwrap_or(16384),
-              parts.remove(0).parse::<i32>().unwrap_or(2048),
-              parts.remove(0).parse::<i32>().unwrap_or(1048576),
-              2560, 300),
-        5 => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              parts.remove(0).parse::<i32>().unwrap_or(16384),
-              parts.remove(0).parse::<i32>().unwrap_or(2048),
-              parts.remove(0).parse::<i32>().unwrap_or(1048576),
-              parts.remove(0).parse::<i32>().unwrap_or(2560), 300),
-        _ => (parts.remove(0).parse::<u64>().unwrap_or(right_now),
-              parts.remove(0).parse::<i32>().unwrap_or(16384),
-              parts.remove(0).parse::<i32>().unwrap_or(2048),
-              parts.remove(0).parse::<i32>().unwrap_or(1048576),
-              parts.remove(0).parse::<i32>().unwrap_or(2560),
-              parts.remove(0).parse::<i32>().unwrap_or(300))
+    let timer = |i: usize, what: &str, default: u32| {
+        number(field(&parts, i), what, default, MAX_TTL, opts)
+    };
+    let numbers = || -> Result<_, String> {
+        Ok((serial()?,
+            timer(1, "SOA refresh", 16384)?,
+            timer(2, "SOA retry", 2048)?,
+            timer(3, "SOA expire", 1048576)?,
+            timer(4, "SOA minimum", 2560)?,
+            timer(5, "TTL", opts.default_ttl)?))
+    };
+    let (ser, refr, retr, exp, min, ttl) = match numbers() {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
+        }
     };
-    // That could probably be a lot cleaner.  Oh well.
 
     // Generate that target string
     let target = format!("{} {} {} {} {} {} {}", ns, contact, ser, refr, 
@@ -260,8 +484,10 @@ pub fn parse_soa(data: &str) -> Vec<TinyDNSRecord> {
     let tdr = TinyDNSRecord {
         rtype:  "SOA".to_string(),
         fqdn:   fqdn.to_string(),
-        target: target,
-        ttl:    ttl
+        target,
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr);
 
@@ -271,10 +497,10 @@ pub fn parse_soa(data: &str) -> Vec<TinyDNSRecord> {
 
 // Parse a combination A/NS/SOA record into 3 TinyDNSRecords
 // .fqdn:ip:x:ttl:timestamp:lo
-// (1) type=NS, fqdn=x(.ns.fqdn), target=fqdn
-// (2) type=A,  fqdn=x(.ns.fqdn), target=ip
-// (3) type=SOA fqdn=fqdn, target="x hostmaster.fqdn default-values"
-pub fn parse_anssoa(data: &str) -> Vec<TinyDNSRecord> {
+// (1) type=NS, fqdn=fqdn, target=x(.ns.fqdn)
+// (2) type=A,  fqdn=x(.ns.fqdn), target=ip (skipped if ip is empty)
+// (3) type=SOA fqdn=fqdn, target="x(.ns.fqdn) hostmaster.fqdn default-values"
+pub fn parse_anssoa(data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create return vec
     let mut retval = Vec::new();
 
@@ -292,20 +518,25 @@ pub fn parse_anssoa(data: &str) -> Vec<TinyDNSRecord> {
     let ip = parts.remove(0); // This can be empty
     let x = parts.remove(0);
 
-    // Make sure IP is an IP
-    match ip.parse::<Ipv4Addr>() {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Error processing record: {}", data);
-            println!("{}", e);
-            return retval;
+    // Make sure IP is an IP, if one was given at all
+    if !ip.is_empty() {
+        match ip.parse::<Ipv4Addr>() {
+            Ok(_) => {},
+            Err(e) => {
+                println!("Error processing record: {}", data);
+                println!("{}", e);
+                return retval;
+            }
         }
     }
 
     // Thankfully there's no big ugly match chains here, just a boolean
-    let ttl = match parts.is_empty() {
-        true => 300,
-        false => parts.remove(0).parse::<i32>().unwrap_or(300)
+    let ttl = match number(field(&parts, 0), "TTL", opts.default_ttl, MAX_TTL, opts) {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
+        }
     };
 
     // As with MX, if x contains a period, it is used as is; otherwise, it
@@ -318,9 +549,11 @@ pub fn parse_anssoa(data: &str) -> Vec<TinyDNSRecord> {
     // Start building TDRs. If ip is empty, don't create (2).
     let tdr1 = TinyDNSRecord {
         rtype:  "NS".to_string(),
-        fqdn:   ns_fqdn.to_string(),
-        target: fqdn.to_string(),
-        ttl:    ttl
+        fqdn:   fqdn.to_string(),
+        target: ns_fqdn.to_string(),
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr1);
 
@@ -329,17 +562,30 @@ pub fn parse_anssoa(data: &str) -> Vec<TinyDNSRecord> {
             rtype:  "A".to_string(),
             fqdn:   ns_fqdn.to_string(),
             target: ip.to_string(),
-            ttl:    ttl
+            ttl,
+            line:   0,
+            source: None
         };
         retval.push(tdr2);
     }
 
-    let target = format!("{} hostmaster.{} 1 1 1 1 60", &ns_fqdn, &fqdn);
+    // tinydns-data gives these the same defaults as a 'Z' line
+    let serial = match opts.default_serial(None) {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return Vec::new();
+        }
+    };
+    let target = format!("{} hostmaster.{} {} 16384 2048 1048576 2560",
+                         &ns_fqdn, &fqdn, serial);
     let tdr3 = TinyDNSRecord {
         rtype:  "SOA".to_string(),
         fqdn:   fqdn.to_string(),
-        target: target,
-        ttl:    ttl
+        target,
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr3);
 
@@ -349,9 +595,9 @@ pub fn parse_anssoa(data: &str) -> Vec<TinyDNSRecord> {
 
 // Parse a combination A/NS record into 2 TinyDNSRecords
 // &fqdn:ip:x:ttl:timestamp:lo
-// (1) type=NS, fqdn=x(.ns.fqdn), target=fqdn
-// (2) type=A,  fqdn=x(.ns.fqdn), target=ip
-pub fn parse_ans(data: &str) -> Vec<TinyDNSRecord> {
+// (1) type=NS, fqdn=fqdn, target=x(.ns.fqdn)
+// (2) type=A,  fqdn=x(.ns.fqdn), target=ip (skipped if ip is empty)
+pub fn parse_ans(data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create return vec
     let mut retval = Vec::new();
 
@@ -369,20 +615,25 @@ pub fn parse_ans(data: &str) -> Vec<TinyDNSRecord> {
     let ip = parts.remove(0);
     let x = parts.remove(0);
 
-    // Make sure IP is an IP
-    match ip.parse::<Ipv4Addr>() {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Error processing record: {}", data);
-            println!("{}", e);
-            return retval;
+    // Make sure IP is an IP, if one was given at all
+    if !ip.is_empty() {
+        match ip.parse::<Ipv4Addr>() {
+            Ok(_) => {},
+            Err(e) => {
+                println!("Error processing record: {}", data);
+                println!("{}", e);
+                return retval;
+            }
         }
     }
 
     // Check for TTL
-    let ttl = match parts.is_empty() {
-        true => 300,
-        false => parts.remove(0).parse::<i32>().unwrap_or(300)
+    let ttl = match number(field(&parts, 0), "TTL", opts.default_ttl, MAX_TTL, opts) {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
+        }
     };
 
     // Check x for dots
@@ -394,19 +645,25 @@ pub fn parse_ans(data: &str) -> Vec<TinyDNSRecord> {
     // Build TDRs
     let tdr1 = TinyDNSRecord {
         rtype:  "NS".to_string(),
-        fqdn:   ns_fqdn.to_string(),
-        target: fqdn.to_string(),
-        ttl:    ttl
+        fqdn:   fqdn.to_string(),
+        target: ns_fqdn.to_string(),
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr1);
 
-    let tdr2 = TinyDNSRecord {
-        rtype:  "A".to_string(),
-        fqdn:   ns_fqdn.to_string(),
-        target: ip.to_string(),
-        ttl:    ttl
-    };
-    retval.push(tdr2);
+    if !ip.is_empty() {
+        let tdr2 = TinyDNSRecord {
+            rtype:  "A".to_string(),
+            fqdn:   ns_fqdn.to_string(),
+            target: ip.to_string(),
+            ttl,
+            line:   0,
+            source: None
+        };
+        retval.push(tdr2);
+    }
 
     // Return
     retval
@@ -416,7 +673,7 @@ pub fn parse_ans(data: &str) -> Vec<TinyDNSRecord> {
 // =fqdn:ip:ttl:timestamp:lo
 // (1) type=A, fqdn=fqdn, target=ip
 // (2) type=PTR, fqdn=arpaized-ip, target=fqdn
-pub fn parse_aptr(data: &str) -> Vec<TinyDNSRecord> {
+pub fn parse_aptr(data: &str, opts: &ParseOptions) -> Vec<TinyDNSRecord> {
     // Create return vec
     let mut retval = Vec::new();
 
@@ -444,23 +701,25 @@ pub fn parse_aptr(data: &str) -> Vec<TinyDNSRecord> {
     };
 
     // TTL check
-    let ttl = match parts.is_empty() {
-        true => 300,
-        false => parts.remove(0).parse::<i32>().unwrap_or(300)
+    let ttl = match number(field(&parts, 0), "TTL", opts.default_ttl, MAX_TTL, opts) {
+        Ok(x) => x,
+        Err(e) => {
+            bad_record(data, &e);
+            return retval;
+        }
     };
 
     // Build a PTR FQDN from the IP
-    let mut ipbits: Vec<&str> = ip.split('.').collect();
-    ipbits.reverse();
-    let backwards = ipbits.join(".");
-    let ptr_fqdn = format!("{}.in-addr.arpa", backwards);
+    let ptr_fqdn = arpa_name(ip);
 
     // Build TDRs
     let tdr1 = TinyDNSRecord {
         rtype:  "A".to_string(),
         fqdn:   fqdn.to_string(),
         target: ip.to_string(),
-        ttl:    ttl
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr1);
 
@@ -468,10 +727,154 @@ pub fn parse_aptr(data: &str) -> Vec<TinyDNSRecord> {
         rtype:  "PTR".to_string(),
         fqdn:   ptr_fqdn,
         target: fqdn.to_string(),
-        ttl:    ttl
+        ttl,
+        line:   0,
+        source: None
     };
     retval.push(tdr2);
 
     // Return
     retval
 }
+
+// Decode the \NNN octal escapes tinydns-data allows in a field
+pub fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 3 < bytes.len() &&
+            bytes[i + 1..i + 4].iter().all(|b| (b'0'..=b'7').contains(b)) {
+            let n = bytes[i + 1..i + 4].iter()
+                .fold(0u32, |acc, b| acc * 8 + u32::from(b - b'0'));
+            if n <= 255 {
+                out.push(n as u8);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Encode a field for a data file, turning colons, backslashes and anything
+// unprintable into \NNN octal escapes so the line splits correctly.
+pub fn escape(field: &str) -> String {
+    let mut out = String::new();
+    for b in field.bytes() {
+        if b == b':' || b == b'\\' || !(0x20..=0x7e).contains(&b) {
+            out.push_str(&format!("\\{:03o}", b));
+        } else {
+            out.push(b as char);
+        }
+    }
+    out
+}
+
+// Build the in-addr.arpa name for an IPv4 address string
+pub fn arpa_name(ip: &str) -> String {
+    let mut ipbits: Vec<&str> = ip.split('.').collect();
+    ipbits.reverse();
+    format!("{}.in-addr.arpa", ipbits.join("."))
+}
+
+// Names in the data may or may not carry a trailing dot and may differ
+// in case, so compare them in a normalised form.
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+// Given a Vec of TinyDNSRecords, produce the lines of a tinydns data file.
+// This is the inverse of from_file: A records with a matching PTR collapse
+// back into '=' lines, MX records take the IP of their exchanger's A record
+// into an '@' line, and NS records do the same for '&' lines.  Anything
+// tinydns can't express is written out as a comment so it isn't lost.
+pub fn to_data(tdrs: &[TinyDNSRecord]) -> Vec<String> {
+    let mut retval = Vec::new();
+    let mut used = vec![false; tdrs.len()];
+
+    // Pair up A and PTR records first.  The PTR has to point back at the
+    // same name and carry the same TTL, since '=' only has the one.
+    let mut aptr = vec![false; tdrs.len()];
+    for (i, a) in tdrs.iter().enumerate() {
+        if a.rtype != "A" {
+            continue;
+        }
+        let arpa = arpa_name(&a.target);
+        let ptr = (0..tdrs.len()).find(|&j| {
+            !used[j] && tdrs[j].rtype == "PTR" && tdrs[j].ttl == a.ttl &&
+                same_name(&tdrs[j].fqdn, &arpa) && same_name(&tdrs[j].target, &a.fqdn)
+        });
+        if let Some(j) = ptr {
+            used[i] = true;
+            used[j] = true;
+            aptr[i] = true;
+        }
+    }
+
+    // Then let MX and NS records claim the A record of their exchanger or
+    // nameserver, if it hasn't already gone into an '=' line.
+    let mut glue: Vec<Option<usize>> = vec![None; tdrs.len()];
+    for (i, rec) in tdrs.iter().enumerate() {
+        if rec.rtype != "MX" && rec.rtype != "NS" {
+            continue;
+        }
+        let host = target_host(rec);
+        let a = (0..tdrs.len()).find(|&j| {
+            !used[j] && tdrs[j].rtype == "A" && tdrs[j].ttl == rec.ttl &&
+                same_name(&tdrs[j].fqdn, &host)
+        });
+        if let Some(j) = a {
+            used[j] = true;
+            glue[i] = Some(j);
+        }
+    }
+
+    // Now walk through in order, writing each record out
+    for (i, rec) in tdrs.iter().enumerate() {
+        if aptr[i] {
+            retval.push(format!("={}:{}:{}", rec.fqdn, rec.target, rec.ttl));
+            continue;
+        }
+        if used[i] {
+            continue;
+        }
+        let ip = match glue[i] {
+            Some(j) => tdrs[j].target.to_string(),
+            None => String::new()
+        };
+        let line = match rec.rtype.as_str() {
+            "A"     => format!("+{}:{}:{}", rec.fqdn, rec.target, rec.ttl),
+            "PTR"   => format!("^{}:{}:{}", rec.fqdn, rec.target, rec.ttl),
+            "CNAME" => format!("C{}:{}:{}", rec.fqdn, rec.target, rec.ttl),
+            "TXT"   => format!("'{}:{}:{}", rec.fqdn, escape(&rec.target), rec.ttl),
+            "MX"    => {
+                let dist = rec.target.split_whitespace().next().unwrap_or("0");
+                format!("@{}:{}:{}:{}:{}", rec.fqdn, ip, target_host(rec), dist, rec.ttl)
+            },
+            "NS"    => format!("&{}:{}:{}:{}", rec.fqdn, ip, rec.target, rec.ttl),
+            "SOA"   => {
+                let fields: Vec<&str> = rec.target.split_whitespace().collect();
+                format!("Z{}:{}:{}", rec.fqdn, fields.join(":"), rec.ttl)
+            },
+            _ => {
+                println!("Warning: can't express {} record for {} in tinydns format",
+                         rec.rtype, rec.fqdn);
+                format!("# {} {} {} {}", rec.fqdn, rec.rtype, rec.target, rec.ttl)
+            }
+        };
+        retval.push(line);
+    }
+
+    retval
+}
+
+// The host an MX ("dist host") or NS ("host") record points at
+fn target_host(rec: &TinyDNSRecord) -> String {
+    match rec.rtype.as_str() {
+        "MX" => rec.target.split_whitespace().nth(1).unwrap_or("").to_string(),
+        _    => rec.target.to_string()
+    }
+}
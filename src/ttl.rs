@@ -0,0 +1,158 @@
+// Define TTL policies: the lowest and highest TTLs records may have, with
+// overrides per record type and per zone, and whether TTLs outside them
+// are clamped into range or rejected.  Policies are read from a YAML (or
+// JSON) file like:
+//
+//   action: clamp
+//   min: 60
+//   max: 86400
+//   types:
+//     NS: { min: 3600 }
+//   zones:
+//     example.com:
+//       min: 300
+//       types:
+//         MX: { min: 3600 }
+use std::collections::BTreeMap;
+use std::fs::File;
+use serde_yaml;
+use types::TinyDNSRecord;
+use zone;
+
+// Rejecting is the default, so nothing changes without being asked to
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all="lowercase")]
+pub enum Action {
+    Clamp,
+    #[default]
+    Reject
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    pub min: Option<u32>,
+    pub max: Option<u32>
+}
+
+// Limits for one zone, and for types within it
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ZonePolicy {
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    #[serde(default)]
+    pub types: BTreeMap<String, Limits>
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub action: Action,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    #[serde(default)]
+    pub types: BTreeMap<String, Limits>,
+    #[serde(default)]
+    pub zones: BTreeMap<String, ZonePolicy>
+}
+
+// A TTL outside the policy, and the TTL it should have instead
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    pub ttl: u32,
+    pub allowed: u32,
+    pub message: String
+}
+
+impl Policy {
+    // Read a policy file, with type names uppercased and zone names
+    // normalised so they match however the file writes them
+    pub fn from_file(fname: &str) -> Result<Self, String> {
+        let f = File::open(fname).map_err(|e| format!("Error opening file {}: {}", fname, e))?;
+        let mut policy: Policy = serde_yaml::from_reader(f)
+            .map_err(|e| format!("Error parsing TTL policy {}: {}", fname, e))?;
+        policy.types = upper_keys(policy.types);
+        policy.zones = policy.zones.into_iter().map(|(k, mut v)| {
+            v.types = upper_keys(v.types);
+            (zone::normalise(&k), v)
+        }).collect();
+        policy.check_ranges().map_err(|e| format!("Error in TTL policy {}: {}", fname, e))?;
+        Ok(policy)
+    }
+
+    // The limits for a record type in a zone.  Each bound comes from the
+    // most specific place that sets it: the zone's entry for the type, the
+    // type, the zone, then the policy as a whole.
+    pub fn limits(&self, rtype: &str, zone: Option<&str>) -> Limits {
+        let zp = zone.and_then(|z| self.zones.get(&zone::normalise(z)));
+        let zt = zp.and_then(|z| z.types.get(rtype));
+        let t = self.types.get(rtype);
+        Limits {
+            min: zt.and_then(|l| l.min).or(t.and_then(|l| l.min))
+                   .or(zp.and_then(|z| z.min)).or(self.min),
+            max: zt.and_then(|l| l.max).or(t.and_then(|l| l.max))
+                   .or(zp.and_then(|z| z.max)).or(self.max)
+        }
+    }
+
+    // Make sure no zone and type the policy names ends up with a minimum
+    // above its maximum, once the bounds are resolved as limits() does
+    fn check_ranges(&self) -> Result<(), String> {
+        let zones = Some(None).into_iter().chain(self.zones.keys().map(|z| Some(z.as_str())));
+        for zone in zones {
+            let mut types: Vec<&str> = vec![""];
+            types.extend(self.types.keys().map(|t| t.as_str()));
+            if let Some(zp) = zone.and_then(|z| self.zones.get(z)) {
+                types.extend(zp.types.keys().map(|t| t.as_str()));
+            }
+            for rtype in types {
+                let (min, max) = match self.limits(rtype, zone) {
+                    Limits { min: Some(min), max: Some(max) } if min > max => (min, max),
+                    _ => continue
+                };
+                let what = match rtype {
+                    "" => "records".to_string(),
+                    x  => format!("{} records", x)
+                };
+                let place = match zone {
+                    Some(z) => format!(" in {}", z),
+                    None => String::new()
+                };
+                return Err(format!("min {} is above max {} for {}{}", min, max, what, place));
+            }
+        }
+        Ok(())
+    }
+
+    // Check a record against the policy, given the zone it's in
+    pub fn check(&self, rec: &TinyDNSRecord, zone: Option<&str>) -> Option<Violation> {
+        let limits = self.limits(&rec.rtype, zone);
+        let (allowed, message) = match (limits.min, limits.max) {
+            (Some(min), _) if rec.ttl < min => {
+                (min, format!("TTL {} is below the {} minimum of {}", rec.ttl, rec.rtype, min))
+            },
+            (_, Some(max)) if rec.ttl > max => {
+                (max, format!("TTL {} is above the {} maximum of {}", rec.ttl, rec.rtype, max))
+            },
+            _ => return None
+        };
+        Some(Violation { ttl: rec.ttl, allowed, message })
+    }
+
+    // Pull every record's TTL into range, whatever the policy's action
+    pub fn clamp(&self, tdrs: &mut [TinyDNSRecord]) {
+        let zones = zone::find_zones(tdrs);
+        for rec in tdrs.iter_mut() {
+            let zname = zone::zone_for(&rec.fqdn, &zones).cloned();
+            if let Some(v) = self.check(rec, zname.as_deref()) {
+                rec.ttl = v.allowed;
+            }
+        }
+    }
+}
+
+fn upper_keys(types: BTreeMap<String, Limits>) -> BTreeMap<String, Limits> {
+    types.into_iter().map(|(k, v)| (k.to_uppercase(), v)).collect()
+}
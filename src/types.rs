@@ -1,29 +1,51 @@
 use std::collections::HashMap;
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Route53File {
   pub resource: HashMap<String, HashMap<String, Route53Record>>
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Route53Record {
   pub zone_id: String,
   pub name: String,
   #[serde(rename="type")]
   pub rtype: String,
   pub records: Vec<String>,
-  pub ttl: i32
+  pub ttl: u32
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TinyDNSRecord {
     pub rtype: String,
     pub fqdn: String,
     pub target: String,
-    pub ttl: i32,
+    pub ttl: u32,
+    // Line of the input file the record came from, or 0 if it didn't come
+    // from a file
+    pub line: usize,
+    // The file that line is in, if it isn't the input file itself (i.e.
+    // it was pulled in by a BIND $INCLUDE)
+    pub source: Option<String>
 }
 
 impl Route53Record {
+    // Create a new record set holding a single value
+    pub fn new(zone_id: &str, name: &str, rtype: &str, record: &str, ttl: u32) -> Self {
+        Route53Record {
+            zone_id: zone_id.to_string(),
+            name:    name.to_string(),
+            rtype:   rtype.to_string(),
+            records: vec![record.to_string()],
+            ttl
+        }
+    }
+
+    // Append another value to this record set
+    pub fn add_record(&mut self, record: &str) {
+        self.records.push(record.to_string());
+    }
+
     // Merge the records vectors of this and another struct
     // Return false if the record types are mismatched or there's
     // any other sorts of issues with the merge
@@ -59,4 +81,3 @@ impl PartialEq for Route53Record {
             self.ttl     == other.ttl
     }
 }
-
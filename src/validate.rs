@@ -0,0 +1,368 @@
+// Define checks for record combinations DNS doesn't allow, run over the
+// parsed records before any output is written so the problems can be
+// traced back to the lines of input that caused them.
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use types::TinyDNSRecord;
+use zone;
+use idn;
+use ttl;
+
+// Settings for the checks that have any
+#[derive(Debug, Clone, Copy)]
+pub struct Options<'a> {
+    // Longest CNAME chain (in hops) allowed before it's reported
+    pub max_cname_depth: usize,
+    // TTL policy to hold records to, if there is one
+    pub ttl_policy: Option<&'a ttl::Policy>,
+    // Make hostname syntax, CNAME conflict and CNAME loop problems errors
+    // rather than warnings.  Data like this converted fine before these checks existed
+    // (names like foo_bar are even accepted by Route53), so only a lint
+    // should fail on it.
+    pub strict: bool
+}
+
+impl<'a> Default for Options<'a> {
+    fn default() -> Self {
+        Options { max_cname_depth: 8, ttl_policy: None, strict: false }
+    }
+}
+
+// Errors stop output being written, warnings are only reported
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all="lowercase")]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+// A line of input: of the input file itself, or of a file it included
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Line {
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub file: Option<String>,
+    pub line: usize
+}
+
+// Something wrong with the records at a name, and the input lines involved
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub name: String,
+    pub message: String,
+    pub lines: Vec<Line>
+}
+
+impl Problem {
+    fn new(name: &str, message: &str, recs: &[&TinyDNSRecord]) -> Self {
+        let mut lines: Vec<Line> = recs.iter().filter(|r| r.line > 0)
+            .map(|r| Line { file: r.source.clone(), line: r.line }).collect();
+        lines.sort();
+        lines.dedup();
+        Problem { severity: Severity::Error, name: name.to_string(),
+                  message: message.to_string(), lines }
+    }
+
+    fn warning(name: &str, message: &str, recs: &[&TinyDNSRecord]) -> Self {
+        Problem { severity: Severity::Warning, ..Problem::new(name, message, recs) }
+    }
+
+    // 'line 3: ...' or 'lines 3, 4: ...', with lines from included files
+    // after those of the input file, e.g. 'line 3; inc.zone line 2: ...'
+    pub fn to_text(&self) -> String {
+        let mut places = Vec::new();
+        let mut files: Vec<Option<&String>> = self.lines.iter().map(|l| l.file.as_ref()).collect();
+        files.dedup();
+        for file in files {
+            let nums: Vec<String> = self.lines.iter().filter(|l| l.file.as_ref() == file)
+                .map(|l| l.line.to_string()).collect();
+            let which = match nums.len() {
+                1 => "line",
+                _ => "lines"
+            };
+            places.push(match file {
+                Some(f) => format!("{} {} {}", f, which, nums.join(", ")),
+                None => format!("{} {}", which, nums.join(", "))
+            });
+        }
+        match places.is_empty() {
+            true => format!("{}: {}", self.name, self.message),
+            false => format!("{}: {}: {}", places.join("; "), self.name, self.message)
+        }
+    }
+}
+
+// Group records by name, ignoring case and trailing dots
+fn by_name(tdrs: &[TinyDNSRecord]) -> BTreeMap<String, Vec<&TinyDNSRecord>> {
+    let mut retval: BTreeMap<String, Vec<&TinyDNSRecord>> = BTreeMap::new();
+    for rec in tdrs {
+        retval.entry(zone::normalise(&rec.fqdn)).or_default().push(rec);
+    }
+    retval
+}
+
+// RFC 1034 section 3.6.2: a name with a CNAME can't have any other data,
+// which also rules out CNAMEs at a zone apex (where the SOA and NS live),
+// and can only have the one CNAME
+pub fn cname_conflicts(tdrs: &[TinyDNSRecord]) -> Vec<Problem> {
+    let apexes = zone::find_zones(tdrs);
+    let mut retval = Vec::new();
+
+    for (name, recs) in by_name(tdrs) {
+        let cnames: Vec<&TinyDNSRecord> = recs.iter().filter(|r| r.rtype == "CNAME")
+                                              .cloned().collect();
+        if cnames.is_empty() {
+            continue;
+        }
+        if cnames.len() > 1 {
+            retval.push(Problem::new(&name, "more than one CNAME", &cnames));
+        }
+        if apexes.contains(&name) {
+            retval.push(Problem::new(&name, "CNAME at a zone apex", &recs));
+            continue;
+        }
+        let mut others: Vec<&str> = recs.iter().filter(|r| r.rtype != "CNAME")
+                                        .map(|r| r.rtype.as_str()).collect();
+        others.sort();
+        others.dedup();
+        if !others.is_empty() {
+            let message = format!("CNAME alongside other records ({})", others.join(", "));
+            retval.push(Problem::new(&name, &message, &recs));
+        }
+    }
+
+    retval
+}
+
+// Follow CNAMEs within the data from each name that starts a chain (i.e.
+// isn't itself a CNAME target), reporting loops, chains longer than
+// max_depth hops, and CNAMEs pointing at names inside one of our zones
+// that have no records at all
+pub fn cname_chains(tdrs: &[TinyDNSRecord], max_depth: usize) -> Vec<Problem> {
+    let zones = zone::find_zones(tdrs);
+    let names = by_name(tdrs);
+    let mut cnames: BTreeMap<String, &TinyDNSRecord> = BTreeMap::new();
+    for rec in tdrs.iter().filter(|r| r.rtype == "CNAME") {
+        cnames.entry(zone::normalise(&rec.fqdn)).or_insert(rec);
+    }
+    let targets: BTreeSet<String> = cnames.values().map(|r| zone::normalise(&r.target)).collect();
+    let mut retval = Vec::new();
+
+    // Dangling targets
+    for (name, rec) in &cnames {
+        let target = zone::normalise(&rec.target);
+        if zone::zone_for(&target, &zones).is_some() && !names.contains_key(&target) {
+            let message = format!("CNAME to {}, which has no records", target);
+            retval.push(Problem::warning(name, &message, &[rec]));
+        }
+    }
+
+    // Walk each chain.  Every name in a loop is also a target, so loops are
+    // found by walking from every CNAME and reported once each.
+    let mut loops: BTreeSet<Vec<String>> = BTreeSet::new();
+    for start in cnames.keys() {
+        let mut chain = vec![start.to_string()];
+        while let Some(rec) = cnames.get(chain.last().unwrap()) {
+            let next = zone::normalise(&rec.target);
+            if let Some(pos) = chain.iter().position(|n| *n == next) {
+                let mut members = chain[pos..].to_vec();
+                members.sort();
+                if loops.insert(members) {
+                    let recs: Vec<&TinyDNSRecord> = chain[pos..].iter().map(|n| cnames[n]).collect();
+                    let message = format!("CNAME loop: {} -> {}", chain[pos..].join(" -> "), next);
+                    retval.push(Problem::new(&chain[pos], &message, &recs));
+                }
+                break;
+            }
+            chain.push(next);
+        }
+
+        let hops = chain.len() - 1;
+        if !targets.contains(start) && hops > max_depth {
+            let recs: Vec<&TinyDNSRecord> = chain.iter().filter_map(|n| cnames.get(n).cloned())
+                                                 .collect();
+            let message = format!("CNAME chain of {} hops, more than {}: {}",
+                                  hops, max_depth, chain.join(" -> "));
+            retval.push(Problem::warning(start, &message, &recs));
+        }
+    }
+
+    retval
+}
+
+// The host a record points at, without the MX preference or SRV priority,
+// weight and port, or None for types whose target isn't a hostname
+fn host_target(rec: &TinyDNSRecord) -> Option<&str> {
+    match rec.rtype.as_str() {
+        "MX" => Some(rec.target.split_whitespace().nth(1).unwrap_or("")),
+        "SRV" => Some(rec.target.split_whitespace().nth(3).unwrap_or("")),
+        "CNAME" | "NS" | "PTR" => Some(rec.target.as_str()),
+        _ => None
+    }
+}
+
+// RFC 2181 section 10.3: MX and NS targets must be hostnames with address
+// records, not aliases.  IP addresses given as the target (usually meant
+// for the IP field of an '@', '&' or '.' line) are flagged too, and targets
+// inside one of our zones need an A or AAAA record there.
+pub fn host_targets(tdrs: &[TinyDNSRecord]) -> Vec<Problem> {
+    let zones = zone::find_zones(tdrs);
+    let names = by_name(tdrs);
+    let mut retval = Vec::new();
+
+    for rec in tdrs.iter().filter(|r| r.rtype == "MX" || r.rtype == "NS") {
+        let name = zone::normalise(&rec.fqdn);
+        let host = host_target(rec).unwrap_or("").trim_end_matches('.');
+        // A null MX (RFC 7505) has no host to check
+        if host.is_empty() {
+            continue;
+        }
+        if host.parse::<IpAddr>().is_ok() {
+            let message = format!("{} target {} is an IP address, not a hostname", rec.rtype, host);
+            retval.push(Problem::new(&name, &message, &[rec]));
+            continue;
+        }
+
+        let target = zone::normalise(host);
+        let recs = match names.get(&target) {
+            Some(x) => x.as_slice(),
+            None => &[]
+        };
+        if let Some(cname) = recs.iter().find(|r| r.rtype == "CNAME") {
+            let message = format!("{} target {} is a CNAME", rec.rtype, target);
+            retval.push(Problem::new(&name, &message, &[rec, cname]));
+        } else if zone::zone_for(&target, &zones).is_some() &&
+                  !recs.iter().any(|r| r.rtype == "A" || r.rtype == "AAAA") {
+            let message = format!("{} target {} has no A or AAAA records", rec.rtype, target);
+            retval.push(Problem::warning(&name, &message, &[rec]));
+        }
+    }
+
+    retval
+}
+
+// What's wrong with a hostname under RFC 1035 and RFC 1123, if anything.
+// Labels may also start with an underscore for service labels (_dmarc,
+// _sip._tcp), and a wildcard is allowed as the leftmost label if
+// 'wildcard' is set.  Names under in-addr.arpa and ip6.arpa aren't
+// hostnames, and RFC 2317 classless delegations put characters like '/'
+// in them, so only their lengths are checked.
+pub fn hostname_problem(host: &str, wildcard: bool) -> Option<String> {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    // Unicode names are checked in the ASCII form they'll be written as
+    if !host.is_ascii() {
+        return match idn::to_ascii(host) {
+            Ok(x) => hostname_problem(&x, wildcard),
+            Err(_) => Some("isn't a valid internationalised name (IDNA 2008/UTS 46)".to_string())
+        };
+    }
+    if host.is_empty() {
+        return Some("is empty".to_string());
+    }
+    if host.len() > 253 {
+        return Some(format!("is {} octets long, more than 253", host.len()));
+    }
+    let lower = host.to_ascii_lowercase();
+    let reverse = lower.ends_with(".in-addr.arpa") || lower.ends_with(".ip6.arpa");
+    for (i, label) in host.split('.').enumerate() {
+        if label.is_empty() {
+            return Some("has an empty label".to_string());
+        }
+        if label.len() > 63 {
+            return Some(format!("has a label of {} octets, more than 63: {}", label.len(), label));
+        }
+        if label == "*" {
+            match (wildcard, i) {
+                (true, 0) => continue,
+                (true, _) => return Some("has a wildcard that isn't the leftmost label".to_string()),
+                (false, _) => return Some("has a wildcard label".to_string())
+            }
+        }
+        if reverse {
+            continue;
+        }
+        let body = label.strip_prefix('_').unwrap_or(label);
+        if body.is_empty() || !body.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Some(format!("has a label with characters other than letters, digits and \
+                                 hyphens (or a leading underscore): {}", label));
+        }
+        if body.starts_with('-') || body.ends_with('-') {
+            return Some(format!("has a label starting or ending with a hyphen: {}", label));
+        }
+        if label.to_ascii_lowercase().starts_with("xn--") && idn::to_unicode(label).is_none() {
+            return Some(format!("has a punycode label that doesn't decode: {}", label));
+        }
+    }
+    None
+}
+
+// Check the syntax of every record's name, and of its target where that's
+// a hostname
+pub fn hostnames(tdrs: &[TinyDNSRecord]) -> Vec<Problem> {
+    let mut retval = Vec::new();
+    for rec in tdrs {
+        let name = zone::normalise(&rec.fqdn);
+        if let Some(reason) = hostname_problem(&rec.fqdn, true) {
+            let message = format!("{} name {}", rec.rtype, reason);
+            retval.push(Problem::new(&name, &message, &[rec]));
+        }
+        match host_target(rec) {
+            // A null MX (RFC 7505) is just "."
+            Some(".") if rec.rtype == "MX" => {},
+            Some(host) => {
+                if let Some(reason) = hostname_problem(host, false) {
+                    let message = format!("{} target {} {}", rec.rtype, host, reason);
+                    retval.push(Problem::new(&name, &message, &[rec]));
+                }
+            },
+            None => {}
+        }
+    }
+    retval
+}
+
+// Report TTLs outside the policy: errors if the policy rejects them, and
+// warnings if they'll be clamped into range
+pub fn ttls(tdrs: &[TinyDNSRecord], policy: &ttl::Policy) -> Vec<Problem> {
+    let zones = zone::find_zones(tdrs);
+    let mut retval = Vec::new();
+    for rec in tdrs {
+        let name = zone::normalise(&rec.fqdn);
+        let v = match policy.check(rec, zone::zone_for(&name, &zones).map(|z| z.as_str())) {
+            Some(x) => x,
+            None => continue
+        };
+        match policy.action {
+            ttl::Action::Reject => retval.push(Problem::new(&name, &v.message, &[rec])),
+            ttl::Action::Clamp => {
+                let message = format!("{}, using {}", v.message, v.allowed);
+                retval.push(Problem::warning(&name, &message, &[rec]));
+            }
+        }
+    }
+    retval
+}
+
+// Problems that are only errors when checking strictly, and warnings
+// otherwise
+fn strict_only(mut problems: Vec<Problem>, strict: bool) -> Vec<Problem> {
+    if !strict {
+        for p in &mut problems {
+            p.severity = Severity::Warning;
+        }
+    }
+    problems
+}
+
+// Run every check, with the problems in input order
+pub fn check(tdrs: &[TinyDNSRecord], options: &Options) -> Vec<Problem> {
+    let mut retval = strict_only(hostnames(tdrs), options.strict);
+    retval.append(&mut strict_only(cname_conflicts(tdrs), options.strict));
+    retval.append(&mut strict_only(cname_chains(tdrs, options.max_cname_depth), options.strict));
+    retval.append(&mut host_targets(tdrs));
+    if let Some(policy) = options.ttl_policy {
+        retval.append(&mut ttls(tdrs, policy));
+    }
+    retval.sort_by(|a, b| a.lines.first().cmp(&b.lines.first()).then(a.name.cmp(&b.name)));
+    retval
+}
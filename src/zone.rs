@@ -0,0 +1,78 @@
+// Define functions for working out which zone a record belongs to, for
+// output formats that are organised per zone rather than as one big list.
+use types::TinyDNSRecord;
+
+// Normalise a name for comparison: lowercase, no trailing dot
+pub fn normalise(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// Turn a hostname into an absolute name with a trailing dot
+pub fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+// Given a Vec of TinyDNSRecords, return the names of the zones they define.
+// A zone is anything with an SOA record, i.e. from a 'Z' or '.' line.
+pub fn find_zones(tdrs: &[TinyDNSRecord]) -> Vec<String> {
+    let mut retval: Vec<String> = Vec::new();
+    for rec in tdrs {
+        if rec.rtype == "SOA" {
+            let zone = normalise(&rec.fqdn);
+            if !retval.contains(&zone) {
+                retval.push(zone);
+            }
+        }
+    }
+    retval
+}
+
+// Is 'name' the zone apex or somewhere underneath it?
+pub fn in_zone(name: &str, zone: &str) -> bool {
+    let name = normalise(name);
+    let zone = normalise(zone);
+    name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+// Find the most specific zone a name falls into, if any.  A delegated
+// sub-zone wins over its parent.
+pub fn zone_for<'a>(name: &str, zones: &'a [String]) -> Option<&'a String> {
+    zones.iter()
+        .filter(|z| in_zone(name, z))
+        .max_by_key(|z| z.len())
+}
+
+// Return 'name' relative to 'zone', or an empty string for the apex itself.
+// Callers are expected to have checked in_zone first.
+pub fn relative_name(name: &str, zone: &str) -> String {
+    let name = name.trim_end_matches('.');
+    let zone = normalise(zone);
+    if name.eq_ignore_ascii_case(&zone) {
+        return String::new();
+    }
+    name[..name.len() - zone.len() - 1].to_string()
+}
+
+// Sort a Vec of TinyDNSRecords into the zones they belong to, in the order
+// the zones were defined.  Records that don't fall into any zone we have an
+// SOA for are handed back separately for the caller to deal with.
+pub fn split_by_zone(tdrs: &[TinyDNSRecord])
+                     -> (Vec<(String, Vec<&TinyDNSRecord>)>, Vec<&TinyDNSRecord>) {
+    let zones = find_zones(tdrs);
+    let mut retval: Vec<(String, Vec<&TinyDNSRecord>)> =
+        zones.iter().map(|z| (z.to_string(), Vec::new())).collect();
+    let mut leftovers = Vec::new();
+
+    for rec in tdrs {
+        match zone_for(&rec.fqdn, &zones) {
+            Some(z) => {
+                // Unwrap is safe, every zone has an entry
+                let idx = zones.iter().position(|x| x == z).unwrap();
+                retval[idx].1.push(rec);
+            },
+            None => leftovers.push(rec)
+        }
+    }
+
+    (retval, leftovers)
+}